@@ -1,6 +1,25 @@
 pub mod llm {
+    pub mod budget;
+    pub mod cache;
     pub mod claude;
+    pub mod cli;
+    pub mod client;
+    pub mod config;
+    pub mod error;
+    pub mod fixture;
+    pub mod gemini;
     pub mod gpt;
+    pub mod input;
+    pub mod ledger;
     pub mod mistral;
+    pub mod ollama;
+    pub mod output;
+    pub mod pricing;
+    pub mod provider;
+    pub mod repl;
+    pub mod report;
+    pub mod retry;
+    pub mod session;
+    pub mod stream;
     pub mod utils;
 }
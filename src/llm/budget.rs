@@ -0,0 +1,114 @@
+use chrono::{Datelike, Local};
+use serde::Deserialize;
+use std::io::{stderr, stdin, IsTerminal, Write};
+
+use crate::llm::config::read_config;
+use crate::llm::error::{LlmError, Result};
+use crate::llm::ledger::read_entries;
+use crate::llm::pricing::get_price;
+use crate::llm::provider::LlmProvider;
+use crate::llm::session::ChatMessage;
+
+/// The budget keys at the top level of the config file, in USD
+#[derive(Deserialize, Debug, Default)]
+struct BudgetConfig {
+    daily_budget: Option<f64>,
+    monthly_budget: Option<f64>,
+    #[serde(default)]
+    budget_action: BudgetAction,
+}
+
+/// What happens to a request that would go over a budget
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum BudgetAction {
+    #[default]
+    Refuse,
+    Confirm,
+}
+
+/// Worst case cost in USD of sending `messages` to `model`, which is the prompt
+/// (about 4 characters per token) and an answer of `max_tokens`
+pub fn estimate_cost(model: &dyn LlmProvider, messages: &[ChatMessage]) -> f64 {
+    let Some(price) = get_price(&model.config().model_name) else {
+        return 0.0;
+    };
+    let prompt_chars: usize = messages
+        .iter()
+        .map(|message| message.content.chars().count())
+        .sum::<usize>()
+        + model
+            .config()
+            .system_prompt()
+            .unwrap_or_default()
+            .chars()
+            .count();
+    let prompt_tokens = prompt_chars.div_ceil(4);
+
+    (prompt_tokens as f64 * price.input + model.config().max_tokens as f64 * price.output)
+        / 1_000_000.0
+}
+
+/// Refuses requests whose estimated cost would go over the daily or monthly budget
+/// spent according to the usage ledger, or asks first if `budget_action` is `confirm`
+pub fn check_budget(estimated_cost: f64) -> Result<()> {
+    // free and unknown models cannot go over any budget
+    if estimated_cost <= 0.0 {
+        return Ok(());
+    }
+
+    let budget_config: BudgetConfig = serde_json::from_value(read_config()?).map_err(|error| {
+        LlmError::Config(format!("Incorrect budget in the config file: {error}"))
+    })?;
+    if budget_config.daily_budget.is_none() && budget_config.monthly_budget.is_none() {
+        return Ok(());
+    }
+
+    let today = Local::now().date_naive();
+    let mut spent_today = 0.0;
+    let mut spent_this_month = 0.0;
+    for entry in read_entries()? {
+        let day = entry.timestamp.with_timezone(&Local).date_naive();
+        let cost = entry.cost.unwrap_or_default();
+        if day == today {
+            spent_today += cost;
+        }
+        if (day.year(), day.month()) == (today.year(), today.month()) {
+            spent_this_month += cost;
+        }
+    }
+
+    let budgets = [
+        ("daily", budget_config.daily_budget, spent_today),
+        ("monthly", budget_config.monthly_budget, spent_this_month),
+    ];
+    for (period, budget, spent) in budgets {
+        let Some(budget) = budget.filter(|budget| spent + estimated_cost > *budget) else {
+            continue;
+        };
+        let message = format!(
+            "This request may cost up to ${estimated_cost:.4}, which would go over the {period} budget of ${budget:.2} (${spent:.4} spent)"
+        );
+        if budget_config.budget_action == BudgetAction::Confirm && confirm(&message) {
+            continue;
+        }
+        return Err(LlmError::Budget(message));
+    }
+    Ok(())
+}
+
+/// Asks on the terminal, without one there is nobody to say yes
+fn confirm(message: &str) -> bool {
+    if !stdin().is_terminal() || !stderr().is_terminal() {
+        return false;
+    }
+
+    // reading blocks, so the runtime has to move other tasks off this thread
+    tokio::task::block_in_place(|| {
+        eprint!("{message}. Send anyway? [y/N] ");
+        let _ = stderr().flush();
+        let mut answer = String::new();
+        stdin().read_line(&mut answer).is_ok()
+            && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+    })
+}
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::llm::config::read_config;
+use crate::llm::error::{LlmError, Result};
+use crate::llm::provider::Completion;
+
+/// How a run uses the response cache
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CacheMode {
+    #[default]
+    Use,
+    /// `-refresh`, asks the providers again and stores the new answers
+    Refresh,
+    /// `-no-cache`, neither reads nor writes the cache
+    Off,
+}
+
+/// The `cache` section of the config file
+#[derive(Deserialize, Debug)]
+struct CacheConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_ttl_hours")]
+    ttl_hours: u64,
+    #[serde(default = "default_max_size_mb")]
+    max_size_mb: u64,
+}
+
+fn default_ttl_hours() -> u64 {
+    24
+}
+
+fn default_max_size_mb() -> u64 {
+    50
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheEntry {
+    created: DateTime<Utc>,
+    completion: Completion,
+}
+
+/// Answers stored on disk, keyed by a hash of the full request
+#[derive(Clone, Debug)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_size: u64,
+    refresh: bool,
+}
+
+impl ResponseCache {
+    /// The cache, unless it is disabled in the config file or with `-no-cache`
+    pub fn open(cache_mode: CacheMode) -> Result<Option<ResponseCache>> {
+        if cache_mode == CacheMode::Off {
+            return Ok(None);
+        }
+
+        let config = read_config()?;
+        if config["cache"].is_null() {
+            return Ok(None);
+        }
+        let cache_config: CacheConfig =
+            serde_json::from_value(config["cache"].clone()).map_err(|error| {
+                LlmError::Config(format!(
+                    "Incorrect cache config in the config file: {error}"
+                ))
+            })?;
+        if !cache_config.enabled {
+            return Ok(None);
+        }
+
+        let mut dir = dirs::cache_dir().ok_or_else(|| {
+            LlmError::Config(String::from("Could not find the user cache directory"))
+        })?;
+        dir.push("llm-cli");
+        dir.push("responses");
+        Ok(Some(ResponseCache {
+            dir,
+            ttl: Duration::from_secs(cache_config.ttl_hours * 3600),
+            max_size: cache_config.max_size_mb * 1024 * 1024,
+            refresh: cache_mode == CacheMode::Refresh,
+        }))
+    }
+
+    /// Hash of the endpoint and body of the request, which hold the model, all parameters
+    /// and the conversation. Headers are left out, they only hold the API key.
+    pub fn key(request: RequestBuilder) -> Option<String> {
+        let request = request.build().ok()?;
+        let mut hasher = Sha256::new();
+        hasher.update(request.method().as_str());
+        hasher.update(" ");
+        hasher.update(request.url().as_str());
+        hasher.update("\n");
+        hasher.update(request.body()?.as_bytes()?);
+        Some(
+            hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        )
+    }
+
+    /// The stored answer, if there is one that is not expired and no refresh was asked for
+    pub fn get(&self, key: &str) -> Option<Completion> {
+        if self.refresh {
+            return None;
+        }
+
+        let json_string = fs::read_to_string(self.entry_path(key)).ok()?;
+        let cache_entry: CacheEntry = serde_json::from_str(&json_string).ok()?;
+        let age = (Utc::now() - cache_entry.created)
+            .to_std()
+            .unwrap_or_default();
+        if age > self.ttl {
+            return None;
+        }
+
+        let mut completion = cache_entry.completion;
+        completion.cached = true;
+        // answering from disk is free
+        completion.cost = Some(0.0);
+        Some(completion)
+    }
+
+    pub fn put(&self, key: &str, completion: &Completion) -> Result<()> {
+        fs::create_dir_all(&self.dir).map_err(|error| {
+            LlmError::Config(format!("Could not create the cache directory: {error}"))
+        })?;
+
+        let cache_entry = CacheEntry {
+            created: Utc::now(),
+            completion: completion.clone(),
+        };
+        let json_string = serde_json::to_string(&cache_entry).map_err(|error| {
+            LlmError::Config(format!("Could not stringify cache entry: {error}"))
+        })?;
+        fs::write(self.entry_path(key), json_string)
+            .map_err(|error| LlmError::Config(format!("Could not write cache entry: {error}")))?;
+
+        self.prune();
+        Ok(())
+    }
+
+    /// Deletes expired entries and then the oldest ones until the cache fits into its size limit
+    fn prune(&self) {
+        let Ok(dir_entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut entries: Vec<(PathBuf, u64, SystemTime)> = dir_entries
+            .filter_map(|dir_entry| {
+                let dir_entry = dir_entry.ok()?;
+                let metadata = dir_entry.metadata().ok()?;
+                Some((dir_entry.path(), metadata.len(), metadata.modified().ok()?))
+            })
+            .collect();
+        // newest first, so everything after the limit is the oldest
+        entries.sort_by_key(|(_, _, modified)| Reverse(*modified));
+
+        let mut size = 0;
+        for (path, len, modified) in entries {
+            let expired = modified.elapsed().unwrap_or_default() > self.ttl;
+            size += len;
+            if expired || size > self.max_size {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
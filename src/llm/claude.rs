@@ -1,15 +1,9 @@
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::ops::Deref;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
 
-use crate::llm::utils::print_response;
-
-use super::utils::get_config_path;
+use crate::llm::error::Result;
+use crate::llm::provider::{Completion, LlmProvider, ProviderConfig};
+use crate::llm::session::ChatMessage;
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Message {
@@ -23,157 +17,85 @@ struct ClaudeBody {
     max_tokens: u16,
     temperature: f32,
     messages: Vec<Message>,
+    stream: bool,
+    /// Anthropic takes the system prompt next to the messages, not as one of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct ClaudeConfig {
-    api_key: String,
-    model_name: String,
-    max_tokens: u16,
-    temperature: f32,
-}
+const BASE_URL: &str = "https://api.anthropic.com/v1";
 
 #[derive(Clone)]
 pub struct CLAUDE {
-    config: ClaudeConfig,
-    endpoint: &'static str,
+    config: ProviderConfig,
 }
 
-impl CLAUDE {
-    fn read_config() -> ClaudeConfig {
-        let config_path = get_config_path();
-        let json_string =
-            fs::read_to_string(config_path).expect("--- Could not read config file ---");
-
-        let json_object: serde_json::Value =
-            serde_json::from_str(&json_string).expect("--- Could not parse JSON ---");
-
-        let config: ClaudeConfig = serde_json::from_value(json_object["claude"].clone())
-            .expect("--- Incorrect Claude config in the config file ---");
-
-        if config.api_key.is_empty() {
-            panic!("--- No Claude API Key provided ---")
-        }
-
-        config
+impl LlmProvider for CLAUDE {
+    fn name(&self) -> &str {
+        "Claude"
     }
-    pub fn parse_args(&mut self, args: Vec<String>) -> String {
-        for (i, arg) in args.iter().enumerate() {
-            if arg == "$" {
-                return args[i + 1..].join(" ");
-            }
-
-            if arg.starts_with("-model=") {
-                self.config.model_name = arg[7..].to_owned();
-                continue;
-            }
-
-            if arg.starts_with("-temp=") {
-                self.config.temperature = arg[6..]
-                    .parse::<f32>()
-                    .expect("--- Could not parse temp to a float ---");
-                continue;
-            }
 
-            if arg.starts_with("-token=") {
-                self.config.max_tokens = arg[7..]
-                    .parse::<u16>()
-                    .expect("--- Could not parse token to a float ---");
-                continue;
-            }
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
 
-            panic!("--- Found invalid argument: {arg} ---")
-        }
-        panic!("--- Missing '$' command ---")
+    fn config_mut(&mut self) -> &mut ProviderConfig {
+        &mut self.config
     }
-    pub async fn make_request(
-        &self,
-        prompt: Arc<String>,
-        request_number: Arc<AtomicUsize>,
-        lock: Arc<Mutex<()>>,
-    ) {
-        // building the body
-        let messages = vec![Message {
-            role: String::from("user"),
-            content: (*prompt).deref().to_owned(),
-        }];
+
+    fn build_request(&self, client: &Client, messages: &[ChatMessage]) -> RequestBuilder {
+        let messages = messages
+            .iter()
+            .map(|message| Message {
+                role: message.role.as_str().to_owned(),
+                content: message.content.clone(),
+            })
+            .collect();
         let body = ClaudeBody {
             temperature: self.config.temperature,
             max_tokens: self.config.max_tokens,
             model: self.config.model_name.clone(),
             messages,
+            stream: self.config.stream,
+            system: self.config.system_prompt().map(str::to_owned),
         };
-        let json_body = serde_json::to_string(&body).unwrap_or_else(|_| {
-            request_number.fetch_sub(1, Ordering::SeqCst);
-            panic!("--- Could not stringify Claude config to JSON ---")
-        });
 
-        // making request as well as measuring time taken
-        let client_builder = Client::builder().timeout(Duration::from_secs(120));
-        let client = client_builder.build().unwrap_or_else(|_| {
-            request_number.fetch_sub(1, Ordering::SeqCst);
-            panic!("--- Could not create Claude client ---")
-        });
-        let req_start = Instant::now();
-        let res = client
-            .post(self.endpoint)
+        client
+            .post(self.config.endpoint(BASE_URL, "/messages"))
             .header("Content-Type", "application/json")
             .header("anthropic-version", "2023-06-01")
             .header("x-api-key", self.config.api_key.as_str())
-            .body(json_body)
-            .send()
-            .await
-            .unwrap_or_else(|_| {
-                request_number.fetch_sub(1, Ordering::SeqCst);
-                panic!("--- Request to Claude endpoint failed ---")
-            });
-
-        // signal to loading loop to stop/decrement
-        request_number.fetch_sub(1, Ordering::SeqCst);
-        let req_time = req_start.elapsed();
+            .json(&body)
+    }
 
-        // if req successful
-        if res.status().is_success() {
-            let response_text = res.text().await.unwrap_or_else(|_| {
-                request_number.fetch_sub(1, Ordering::SeqCst);
-                panic!("--- Failed parsing Claude response message ---")
-            });
-            let parsed_response_text: serde_json::Value = serde_json::from_str(&response_text)
-                .unwrap_or_else(|_| {
-                    request_number.fetch_sub(1, Ordering::SeqCst);
-                    panic!("--- Failed parsing Claude response message ---")
-                });
-            let response_text: String =
-                serde_json::from_value(parsed_response_text["content"][0]["text"].clone())
-                    .unwrap_or_else(|_| {
-                        request_number.fetch_sub(1, Ordering::SeqCst);
-                        panic!("--- Malformed Claude JSON response ---")
-                    });
+    fn parse_response(&self, response: &serde_json::Value) -> Option<String> {
+        serde_json::from_value(response["content"][0]["text"].clone()).ok()
+    }
 
-            let _lock = lock.lock().await;
-            print_response(&response_text, req_time.as_secs_f64(), 58, "Claude");
-            return;
+    fn parse_stream_event(&self, event: &serde_json::Value) -> Option<String> {
+        if event["type"] != "content_block_delta" {
+            return None;
         }
+        event["delta"]["text"].as_str().map(str::to_owned)
+    }
 
-        // if something went wrong
-        let _lock = lock.lock().await;
-        request_number.fetch_sub(1, Ordering::SeqCst);
-        panic!(
-            "--- Request to Claude failed with: ---\nStatus Code: {}\nError Message: {}",
-            res.status(),
-            res.text().await.unwrap_or_else(|_| {
-                request_number.fetch_sub(1, Ordering::SeqCst);
-                panic!("--- Failed parsing Claude response message ---")
-            })
-        )
+    fn parse_metadata(&self, response: &serde_json::Value, completion: &mut Completion) {
+        // streams report the input tokens at the start and the rest in the final message_delta
+        let (message, stop_reason) = match response["type"].as_str() {
+            Some("message_start") => (&response["message"], &response["message"]["stop_reason"]),
+            Some("message_delta") => (response, &response["delta"]["stop_reason"]),
+            _ => (response, &response["stop_reason"]),
+        };
+        completion.set_finish_reason(stop_reason);
+        completion.set_usage(
+            &message["usage"]["input_tokens"],
+            &message["usage"]["output_tokens"],
+        );
     }
 }
-impl Default for CLAUDE {
-    fn default() -> Self {
-        let config = CLAUDE::read_config();
-        CLAUDE {
-            endpoint: "https://api.anthropic.com/v1/messages",
-            config,
-        }
+impl CLAUDE {
+    pub fn new() -> Result<Self> {
+        let config = ProviderConfig::read("claude", "Claude", Some("ANTHROPIC_API_KEY"))?;
+        Ok(CLAUDE { config })
     }
 }
@@ -0,0 +1,467 @@
+use std::path::Path;
+
+use crate::llm::cache::CacheMode;
+use crate::llm::config::{get_config_paths, read_config};
+use crate::llm::error::{LlmError, Result};
+use crate::llm::output::OutputFormat;
+use crate::llm::provider::provider_names;
+
+const HELP: &str = "\
+Ask LLMs from the command line
+
+Usage:
+  llm-cli <models> [options] [$ <prompt>]     ask one model, compare several or chat without a prompt
+  llm-cli ask <model> [options] $ <prompt>    ask a single model
+  llm-cli chat <model> [options] [$ <prompt>] chat with a single model
+  llm-cli compare <models> [options] $ <prompt>
+                                              ask several models in parallel
+  llm-cli models                              list the models you can use
+  llm-cli config                              show the config files and what they set
+  llm-cli usage [options]                     sum up the requests made so far
+  llm-cli help [<command>]                    show this help or the one of a command
+
+Models:
+  gpt,claude              several models, separated by ','
+  gpt:gpt-4o              a specific model of a provider
+  gpt[temp=0.2,token=500] parameters for this model only
+
+Options:
+  -model=x        the model name, e.g. gpt-4o
+  -temp=x         the temperature, between 0 and 2
+  -token=x        the maximum number of tokens of the answer
+  -system=x       a system prompt, an empty one turns it off
+  -file=x         attach a file to the prompt, can be given several times
+  -format=x       text (the default), json or ndjson
+  -session=x      continue (or start) the conversation x, single model only
+  -i              chat after sending the prompt, single model only
+  -strict         fail if any model fails, not only if all of them do
+  -no-cache       neither use nor store cached answers
+  -refresh        ask again even if a cached answer exists
+  -h, --help      show this help
+  -V, --version   show the version
+
+Everything after '$' is the prompt. Input piped into the command is attached to it.";
+
+const ASK_HELP: &str = "\
+Ask a single model
+
+Usage:
+  llm-cli ask <model> [options] $ <prompt>
+  cat notes.txt | llm-cli ask <model> [options] [$ <prompt>]
+
+The prompt can also come from piped input or -file=x alone. Takes the options of 'llm-cli --help',
+e.g. llm-cli ask claude -temp=0.5 $ give me 20 places to visit in Tokyo";
+
+const CHAT_HELP: &str = "\
+Chat with a single model, keeping the conversation across turns
+
+Usage:
+  llm-cli chat <model> [options] [$ <first prompt>]
+
+Takes the options of 'llm-cli --help' except -format. Type /help in the chat for its commands,
+e.g. llm-cli chat gpt -session=tokyo";
+
+const COMPARE_HELP: &str = "\
+Ask several models in parallel and compare their answers
+
+Usage:
+  llm-cli compare <models> [options] $ <prompt>
+
+Takes the options of 'llm-cli --help' except -session and -i,
+e.g. llm-cli compare gpt:gpt-4o,gpt:gpt-4o-mini,claude -token=500 $ explain monads";
+
+const CONFIG_HELP: &str = "\
+Show the config files in the order they are merged and the resulting config,
+with API keys hidden
+
+Usage:
+  llm-cli config";
+
+const MODELS_HELP: &str = "\
+List the models you can use, including the ones defined in the config file,
+together with the model name they use by default
+
+Usage:
+  llm-cli models";
+
+const USAGE_HELP: &str = "\
+Sum up the requests recorded in the usage ledger
+
+Usage:
+  llm-cli usage [-by=day|model|provider] [-since=<date>|<n>d] [-format=text|csv]
+
+e.g. llm-cli usage -by=model -since=7d";
+
+/// What the command line asks for
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// `<models> ...` without a command, a single model is asked (or chatted with if there
+    /// is no prompt) and several ones are compared
+    Run {
+        models: String,
+        args: Vec<String>,
+    },
+    Ask {
+        model: String,
+        args: Vec<String>,
+    },
+    Chat {
+        model: String,
+        args: Vec<String>,
+    },
+    Compare {
+        models: String,
+        args: Vec<String>,
+    },
+    Config,
+    Models,
+    Usage(Vec<String>),
+    Help(&'static str),
+    Version,
+}
+
+/// Names of the commands, a provider with the same name can only be used after one of them
+const COMMANDS: &[&str] = &[
+    "ask", "chat", "compare", "config", "models", "usage", "help",
+];
+
+/// Parses the arguments including the program name. Invoked through a link like `gpt`,
+/// the name of the link is the model.
+pub fn parse_command(mut args: Vec<String>) -> Result<Command> {
+    let program_name = args
+        .first()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|program_name| program_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // on windows the program and its links end in .exe, other dots belong to the model name
+    let program_name = program_name
+        .strip_suffix(".exe")
+        .unwrap_or(&program_name)
+        .to_string();
+    if !args.is_empty() {
+        args.remove(0);
+    }
+    if !program_name.is_empty() && program_name != "llm-cli" {
+        args.insert(0, program_name);
+    }
+
+    let Some(first_arg) = args.first().cloned() else {
+        return Ok(Command::Help(HELP));
+    };
+    let other_args = args[1..].to_vec();
+    // help is only asked for before the prompt, after it `-h` is part of the question
+    let wants_help = other_args
+        .iter()
+        .take_while(|arg| *arg != "$")
+        .any(|arg| arg == "-h" || arg == "--help");
+
+    match first_arg.as_str() {
+        "-h" | "--help" => Ok(Command::Help(HELP)),
+        "-V" | "--version" => Ok(Command::Version),
+        "help" => match other_args.first() {
+            Some(command) => Ok(Command::Help(command_help(command)?)),
+            None => Ok(Command::Help(HELP)),
+        },
+        command if wants_help && COMMANDS.contains(&command) => {
+            Ok(Command::Help(command_help(command)?))
+        }
+        _ if wants_help => Ok(Command::Help(HELP)),
+        "config" => no_args("config", &other_args).map(|_| Command::Config),
+        "models" => no_args("models", &other_args).map(|_| Command::Models),
+        "usage" => Ok(Command::Usage(other_args)),
+        "ask" => {
+            let (model, args) = model_args("ask", other_args)?;
+            Ok(Command::Ask { model, args })
+        }
+        "chat" => {
+            let (model, args) = model_args("chat", other_args)?;
+            Ok(Command::Chat { model, args })
+        }
+        "compare" => {
+            let (models, args) = model_args("compare", other_args)?;
+            Ok(Command::Compare { models, args })
+        }
+        "$" => Err(LlmError::Usage(String::from(
+            "Missing model name before '$', e.g. llm-cli gpt $ <prompt>",
+        ))),
+        option if option.starts_with('-') => Err(LlmError::Usage(format!(
+            "Unknown option '{option}', see 'llm-cli --help'"
+        ))),
+        _ => Ok(Command::Run {
+            models: first_arg,
+            args: other_args,
+        }),
+    }
+}
+
+fn command_help(command: &str) -> Result<&'static str> {
+    match command {
+        "ask" => Ok(ASK_HELP),
+        "chat" => Ok(CHAT_HELP),
+        "compare" => Ok(COMPARE_HELP),
+        "config" => Ok(CONFIG_HELP),
+        "models" => Ok(MODELS_HELP),
+        "usage" => Ok(USAGE_HELP),
+        "help" => Ok(HELP),
+        _ => Err(LlmError::Usage(format!(
+            "Unknown command '{command}', choose between '{}'",
+            COMMANDS.join("', '")
+        ))),
+    }
+}
+
+/// Splits off the model list that has to follow `command`
+fn model_args(command: &str, mut args: Vec<String>) -> Result<(String, Vec<String>)> {
+    match args.first() {
+        Some(models) if models != "$" && !models.starts_with('-') => Ok((args.remove(0), args)),
+        _ => Err(LlmError::Usage(format!(
+            "Missing model name after '{command}', e.g. llm-cli {command} gpt $ <prompt>"
+        ))),
+    }
+}
+
+fn no_args(command: &str, args: &[String]) -> Result<()> {
+    match args.first() {
+        Some(arg) => Err(LlmError::Usage(format!(
+            "'{command}' takes no arguments, found '{arg}'"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Everything besides the provider config that is given on the command line
+#[derive(Default)]
+pub struct PromptArgs {
+    /// Everything after `$`, `None` if there is no `$`
+    pub prompt: Option<String>,
+    pub session: Option<String>,
+    pub interactive: bool,
+    pub format: OutputFormat,
+    /// Paths given with `-file=`, attached to the prompt in this order
+    pub files: Vec<String>,
+    /// `MODEL_PARAMS` given as flags where the arguments are shared by several models
+    pub params: Vec<(String, String)>,
+    /// Fail if any of several models fails, not only if all of them do
+    pub strict: bool,
+    /// Set with `-no-cache` or `-refresh`
+    pub cache_mode: CacheMode,
+}
+
+/// Parameters of a single model, given as `-temp=0.5` or per model as `gpt[temp=0.5]`
+pub const MODEL_PARAMS: &[&str] = &["model", "temp", "token", "system"];
+
+/// Splits flags like `-temp=0.5` into name and value, if they set one of the `MODEL_PARAMS`
+fn parse_param_arg(arg: &str) -> Option<(&str, &str)> {
+    arg.strip_prefix('-')
+        .and_then(|arg| arg.split_once('='))
+        .filter(|(name, _)| MODEL_PARAMS.contains(name))
+}
+
+/// One entry of the comma separated model list, like `gpt`, `gpt:gpt-4o`
+/// or `gpt[model=gpt-4o,temp=0.2]`
+pub struct ModelSpec {
+    pub provider: String,
+    /// Model name given after `:`
+    pub model: Option<String>,
+    /// `MODEL_PARAMS` in brackets, they only apply to this model
+    pub params: Vec<(String, String)>,
+}
+
+/// Splits the model list at the commas outside of brackets
+pub fn parse_model_list(model_list: &str) -> Result<Vec<ModelSpec>> {
+    let mut entries = Vec::new();
+    let mut entry = String::new();
+    let mut in_brackets = false;
+    for c in model_list.chars() {
+        match c {
+            '[' if in_brackets => {
+                return Err(LlmError::Usage(format!("Nested '[' in '{model_list}'")));
+            }
+            ']' if !in_brackets => {
+                return Err(LlmError::Usage(format!("Unexpected ']' in '{model_list}'")));
+            }
+            '[' | ']' => in_brackets = !in_brackets,
+            ',' if !in_brackets => {
+                entries.push(entry);
+                entry = String::new();
+                continue;
+            }
+            _ => {}
+        }
+        entry.push(c);
+    }
+    if in_brackets {
+        return Err(LlmError::Usage(format!("Missing ']' in '{model_list}'")));
+    }
+    entries.push(entry);
+
+    entries
+        .iter()
+        .map(|entry| parse_model_spec(entry))
+        .collect()
+}
+
+fn parse_model_spec(entry: &str) -> Result<ModelSpec> {
+    let (provider, params) = entry.split_once('[').unwrap_or((entry, "]"));
+    let params = params
+        .strip_suffix(']')
+        .ok_or_else(|| LlmError::Usage(format!("Found text after the ']' of '{entry}'")))?;
+
+    let params = params
+        .split(',')
+        .filter(|param| !param.trim().is_empty())
+        .map(|param| match param.split_once('=') {
+            Some((name, _)) if !MODEL_PARAMS.contains(&name.trim()) => {
+                Err(LlmError::Usage(format!(
+                    "Unknown parameter '{}' of '{provider}', choose between '{}'",
+                    name.trim(),
+                    MODEL_PARAMS.join("', '")
+                )))
+            }
+            Some((name, value)) => Ok((name.trim().to_owned(), value.trim().to_owned())),
+            None => Err(LlmError::Usage(format!(
+                "Parameter '{param}' of '{provider}' is missing a value, use name=value"
+            ))),
+        })
+        .collect::<Result<_>>()?;
+
+    let (provider, model) = match provider.split_once(':') {
+        Some((provider, model)) if !model.is_empty() => (provider, Some(model.to_owned())),
+        Some(_) => {
+            return Err(LlmError::Usage(format!(
+                "Missing the model name after ':' in '{entry}'"
+            )))
+        }
+        None => (provider, None),
+    };
+    Ok(ModelSpec {
+        provider: provider.to_owned(),
+        model,
+        params,
+    })
+}
+
+/// Parses the flags and the prompt after the model list, the params apply to every model
+pub fn parse_prompt(args: Vec<String>) -> Result<PromptArgs> {
+    let mut prompt_args = PromptArgs::default();
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "$" {
+            prompt_args.prompt = Some(args[i + 1..].join(" "));
+            return Ok(prompt_args);
+        }
+
+        if arg == "-i" {
+            prompt_args.interactive = true;
+            continue;
+        }
+
+        if let Some(session_name) = arg.strip_prefix("-session=") {
+            prompt_args.session = Some(session_name.to_owned());
+            continue;
+        }
+
+        if let Some(format) = arg.strip_prefix("-format=") {
+            prompt_args.format = OutputFormat::parse(format)?;
+            continue;
+        }
+
+        if let Some((name, value)) = parse_param_arg(arg) {
+            prompt_args.params.push((name.to_owned(), value.to_owned()));
+            continue;
+        }
+
+        if let Some(file) = arg.strip_prefix("-file=") {
+            prompt_args.files.push(file.to_owned());
+            continue;
+        }
+
+        if arg == "-strict" {
+            prompt_args.strict = true;
+            continue;
+        }
+
+        if arg == "-no-cache" {
+            prompt_args.cache_mode = CacheMode::Off;
+            continue;
+        }
+
+        if arg == "-refresh" {
+            prompt_args.cache_mode = CacheMode::Refresh;
+            continue;
+        }
+
+        return Err(LlmError::Usage(invalid_argument(arg)));
+    }
+
+    // without `$` the prompt may still come from stdin or files
+    Ok(prompt_args)
+}
+
+/// Says what is wrong with an argument, so typos don't end up as a generic error
+fn invalid_argument(arg: &str) -> String {
+    if !arg.starts_with('-') {
+        return format!("Unexpected argument '{arg}', the prompt goes after '$'");
+    }
+    let name = arg.trim_start_matches('-');
+    let name = name.split_once('=').map_or(name, |(name, _)| name);
+    match MODEL_PARAMS.contains(&name) {
+        true => format!("Missing value in '{arg}', e.g. -{name}=x"),
+        false => format!("Unknown option '{arg}', see 'llm-cli --help'"),
+    }
+}
+
+pub fn print_help(help: &str) {
+    println!("{help}");
+}
+
+pub fn print_version() {
+    println!("llm-cli {}", env!("CARGO_PKG_VERSION"));
+}
+
+/// `llm-cli config`, the merged config is printed with API keys hidden so it can be shared
+pub fn run_config_command() -> Result<()> {
+    println!("Config files, later ones override earlier ones:");
+    for config_path in get_config_paths()? {
+        println!("  {}", config_path.display());
+    }
+
+    let mut config = read_config()?;
+    hide_api_keys(&mut config);
+    println!(
+        "\n{}",
+        serde_json::to_string_pretty(&config).unwrap_or_default()
+    );
+    Ok(())
+}
+
+fn hide_api_keys(config: &mut serde_json::Value) {
+    let serde_json::Value::Object(config) = config else {
+        return;
+    };
+    for (key, value) in config {
+        match value {
+            serde_json::Value::String(api_key) if key == "api_key" && !api_key.is_empty() => {
+                *api_key = String::from("********");
+            }
+            _ => hide_api_keys(value),
+        }
+    }
+}
+
+/// `llm-cli models`, every provider with the model it asks unless told otherwise
+pub fn run_models_command() -> Result<()> {
+    let config = read_config()?;
+    let names = provider_names();
+    let name_width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or_default();
+    for name in &names {
+        let model_name = config[name]["model_name"]
+            .as_str()
+            .unwrap_or("(not configured)");
+        println!("{name:<name_width$}  {model_name}");
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use reqwest::{Certificate, Client, NoProxy, Proxy};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::llm::config::read_config;
+use crate::llm::error::{LlmError, Result};
+use crate::llm::provider::ProviderConfig;
+
+/// The `http` section of the config file, for networks that need a proxy or their own CA
+#[derive(Deserialize, Debug, Default)]
+struct HttpConfig {
+    /// Used for all requests, without it `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY` are used
+    proxy: Option<String>,
+    /// Hosts reached without the proxy, like `localhost,.corp.example`, defaults to `NO_PROXY`
+    no_proxy: Option<String>,
+    /// PEM file with root certificates to trust besides the ones of the system
+    ca_bundle: Option<String>,
+}
+
+/// Clients of this run by connect timeout, so all requests share their connections
+static CLIENTS: Mutex<Vec<(u64, Client)>> = Mutex::new(Vec::new());
+
+/// The shared client for requests of a provider with the given config
+pub fn get_client(config: &ProviderConfig) -> Result<Client> {
+    let mut clients = CLIENTS.lock().unwrap();
+    if let Some((_, client)) = clients
+        .iter()
+        .find(|(connect_timeout_secs, _)| *connect_timeout_secs == config.connect_timeout_secs)
+    {
+        return Ok(client.clone());
+    }
+
+    let client = build_client(Duration::from_secs(config.connect_timeout_secs))?;
+    clients.push((config.connect_timeout_secs, client.clone()));
+    Ok(client)
+}
+
+fn build_client(connect_timeout: Duration) -> Result<Client> {
+    let config = read_config()?;
+    let http_config: HttpConfig = match config["http"].is_null() {
+        true => HttpConfig::default(),
+        false => serde_json::from_value(config["http"].clone()).map_err(|error| {
+            LlmError::Config(format!("Incorrect http config in the config file: {error}"))
+        })?,
+    };
+
+    // there is no overall timeout, long answers are cut off by the read timeout of each provider
+    let mut client_builder = Client::builder().connect_timeout(connect_timeout);
+
+    let no_proxy = || match &http_config.no_proxy {
+        Some(no_proxy) => NoProxy::from_string(no_proxy),
+        None => NoProxy::from_env(),
+    };
+    if let Some(proxy_url) = &http_config.proxy {
+        let proxy = Proxy::all(proxy_url)
+            .map_err(|error| LlmError::Config(format!("Invalid proxy '{proxy_url}': {error}")))?
+            .no_proxy(no_proxy());
+        client_builder = client_builder.proxy(proxy);
+    } else if http_config.no_proxy.is_some() {
+        // reqwest only applies NO_PROXY to the proxies it takes from the environment itself
+        for proxy in env_proxies()? {
+            client_builder = client_builder.proxy(proxy.no_proxy(no_proxy()));
+        }
+    }
+
+    if let Some(ca_bundle) = &http_config.ca_bundle {
+        let pem_bundle = fs::read(ca_bundle).map_err(|error| {
+            LlmError::Config(format!("Could not read the CA bundle {ca_bundle}: {error}"))
+        })?;
+        let certificates = Certificate::from_pem_bundle(&pem_bundle).map_err(|error| {
+            LlmError::Config(format!("Invalid certificate in {ca_bundle}: {error}"))
+        })?;
+        for certificate in certificates {
+            client_builder = client_builder.add_root_certificate(certificate);
+        }
+    }
+
+    client_builder
+        .build()
+        .map_err(|error| LlmError::Config(format!("Could not set up the HTTP client: {error}")))
+}
+
+type ProxyConstructor = fn(String) -> reqwest::Result<Proxy>;
+
+/// The proxies of the environment in the order reqwest would use them
+fn env_proxies() -> Result<Vec<Proxy>> {
+    let env_proxies: [(&str, ProxyConstructor); 3] = [
+        ("HTTPS_PROXY", Proxy::https),
+        ("HTTP_PROXY", Proxy::http),
+        ("ALL_PROXY", Proxy::all),
+    ];
+    let mut proxies = Vec::new();
+    for (name, constructor) in env_proxies {
+        let Some(proxy_url) = [name.to_owned(), name.to_lowercase()]
+            .iter()
+            .find_map(|name| {
+                env::var(name)
+                    .ok()
+                    .filter(|proxy_url| !proxy_url.is_empty())
+            })
+        else {
+            continue;
+        };
+        let proxy = constructor(proxy_url.clone()).map_err(|error| {
+            LlmError::Config(format!("Invalid proxy '{proxy_url}' in {name}: {error}"))
+        })?;
+        proxies.push(proxy);
+    }
+    Ok(proxies)
+}
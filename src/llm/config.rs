@@ -0,0 +1,136 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::llm::error::{LlmError, Result};
+
+/// All config files that exist, from the lowest to the highest precedence:
+/// `/etc`, `~/.config/llm-cli`, `$XDG_CONFIG_HOME/llm-cli` and `$LLM_CLI_CONFIG`.
+/// With `$LLM_CLI_CONFIG_ONLY` set, `$LLM_CLI_CONFIG` is the only one.
+pub fn get_config_paths() -> Result<Vec<PathBuf>> {
+    let mut config_paths = Vec::new();
+    if cfg!(unix) {
+        config_paths.push(PathBuf::from("/etc/llm_cli_config.json"));
+    }
+    if let Some(mut home_config_path) = dirs::home_dir() {
+        home_config_path.push(".config/llm-cli/config.json");
+        config_paths.push(home_config_path);
+    }
+    if let Some(xdg_config_home) = env::var_os("XDG_CONFIG_HOME").filter(|path| !path.is_empty()) {
+        let mut xdg_config_path = PathBuf::from(xdg_config_home);
+        xdg_config_path.push("llm-cli/config.json");
+        config_paths.push(xdg_config_path);
+    }
+    config_paths.retain(|config_path| config_path.is_file());
+
+    // with LLM_CLI_CONFIG_ONLY nothing of the machine gets in, e.g. for tests
+    if env::var_os("LLM_CLI_CONFIG_ONLY").is_some_and(|only| !only.is_empty()) {
+        config_paths.clear();
+    }
+
+    // an explicitly given file has to exist
+    if let Some(env_config_path) = env::var_os("LLM_CLI_CONFIG").filter(|path| !path.is_empty()) {
+        let env_config_path = PathBuf::from(env_config_path);
+        if !env_config_path.is_file() {
+            return Err(LlmError::Config(format!(
+                "LLM_CLI_CONFIG points to {}, which does not exist",
+                env_config_path.display()
+            )));
+        }
+        config_paths.push(env_config_path);
+    }
+
+    // $XDG_CONFIG_HOME is usually ~/.config, no need to read the file twice
+    let mut seen_paths = Vec::new();
+    config_paths.retain(|config_path| {
+        let canonical_path = fs::canonicalize(config_path).unwrap_or(config_path.clone());
+        if seen_paths.contains(&canonical_path) {
+            return false;
+        }
+        seen_paths.push(canonical_path);
+        true
+    });
+
+    if config_paths.is_empty() {
+        return Err(LlmError::Config(String::from(
+            "No config file found, create ~/.config/llm-cli/config.json or /etc/llm_cli_config.json",
+        )));
+    }
+    Ok(config_paths)
+}
+
+/// Reads all config files and merges them, later files override single keys of earlier ones
+pub fn read_config() -> Result<serde_json::Value> {
+    let mut config = serde_json::Value::Object(serde_json::Map::new());
+    for config_path in get_config_paths()? {
+        let json_string = fs::read_to_string(&config_path).map_err(|error| {
+            LlmError::Config(format!(
+                "Could not read config file {}: {error}",
+                config_path.display()
+            ))
+        })?;
+        let json_object: serde_json::Value =
+            serde_json::from_str(&json_string).map_err(|error| {
+                LlmError::Config(format!(
+                    "Could not parse JSON in {}: {error}",
+                    config_path.display()
+                ))
+            })?;
+        merge_json(&mut config, json_object);
+    }
+    Ok(config)
+}
+
+/// Deep merges `overlay` into `base`, objects are merged key by key, everything else is replaced
+pub fn merge_json(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_json(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Picks the API key from the environment variable `api_key_env`, the output of
+/// `api_key_cmd` or the plain `api_key` from the config file, in this order
+pub fn resolve_api_key(
+    label: &str,
+    api_key_env: &str,
+    api_key: &str,
+    api_key_cmd: Option<&str>,
+) -> Result<String> {
+    if let Some(env_api_key) = env::var(api_key_env).ok().filter(|key| !key.is_empty()) {
+        return Ok(env_api_key);
+    }
+
+    let Some(api_key_cmd) = api_key_cmd.filter(|cmd| !cmd.trim().is_empty()) else {
+        return Ok(api_key.to_owned());
+    };
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(api_key_cmd)
+        .output()
+        .map_err(|error| {
+            LlmError::Auth(format!("Could not run the {label} api_key_cmd: {error}"))
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(LlmError::Auth(format!(
+            "The {label} api_key_cmd failed with {} {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+
+    // only the first line counts, `pass` for example stores more below the password
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().next().unwrap_or_default().trim().to_owned())
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+
+/// Everything that can go wrong, grouped by what the user has to do about it
+#[derive(Debug)]
+pub enum LlmError {
+    /// Missing or broken config file (or other local files like sessions)
+    Config(String),
+    /// Missing API key or a key the provider rejected
+    Auth(String),
+    /// The provider answered with an error status
+    Http {
+        provider: String,
+        status: u16,
+        message: String,
+    },
+    /// The provider could not be reached or the connection broke
+    Network(String),
+    /// The provider answered with something we could not understand
+    Parse(String),
+    /// Invalid command line input
+    Usage(String),
+    /// The request would go over a spending budget
+    Budget(String),
+    /// Some of several models failed, each of them was already reported on its own
+    Failed(String),
+}
+
+pub type Result<T> = std::result::Result<T, LlmError>;
+
+impl LlmError {
+    /// Process exit code, so scripts can tell the categories apart
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            LlmError::Failed(_) => 1,
+            LlmError::Usage(_) => 2,
+            LlmError::Config(_) => 3,
+            LlmError::Auth(_) => 4,
+            LlmError::Http { .. } => 5,
+            LlmError::Network(_) => 6,
+            LlmError::Parse(_) => 7,
+            LlmError::Budget(_) => 8,
+        }
+    }
+
+    /// Maps an error status of `provider` to the matching category
+    pub fn from_status(provider: &str, status: u16, message: String) -> LlmError {
+        match status {
+            401 | 403 => LlmError::Auth(format!(
+                "{provider} rejected the API key (status {status}): {message}"
+            )),
+            _ => LlmError::Http {
+                provider: provider.to_owned(),
+                status,
+                message,
+            },
+        }
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Config(message) => write!(f, "Config error: {message}"),
+            LlmError::Auth(message) => write!(f, "Authentication error: {message}"),
+            LlmError::Http {
+                provider,
+                status,
+                message,
+            } => write!(
+                f,
+                "Request to {provider} failed with status {status}: {message}"
+            ),
+            LlmError::Network(message) => write!(f, "Network error: {message}"),
+            LlmError::Parse(message) => write!(f, "Parse error: {message}"),
+            LlmError::Usage(message) => write!(f, "Usage error: {message}"),
+            LlmError::Budget(message) => write!(f, "Budget exceeded: {message}"),
+            LlmError::Failed(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
@@ -0,0 +1,152 @@
+use reqwest::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::llm::error::{LlmError, Result};
+
+/// Set with `LLM_CLI_RECORD=<dir>` or `LLM_CLI_REPLAY=<dir>`, used to build offline tests
+/// from real conversations
+pub enum FixtureMode {
+    /// Sends requests as usual and saves every request with its response into the directory
+    Record(PathBuf),
+    /// Answers requests from the directory without any network access
+    Replay(PathBuf),
+}
+
+impl FixtureMode {
+    pub fn from_env() -> Option<FixtureMode> {
+        if let Some(dir) = env::var_os("LLM_CLI_REPLAY").filter(|dir| !dir.is_empty()) {
+            return Some(FixtureMode::Replay(PathBuf::from(dir)));
+        }
+        env::var_os("LLM_CLI_RECORD")
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| FixtureMode::Record(PathBuf::from(dir)))
+    }
+}
+
+/// A recorded request and the response the provider gave to it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Fixture {
+    pub request: FixtureRequest,
+    pub response: FixtureResponse,
+}
+
+/// The parts of a request that identify it. Headers are left out as they hold the API key,
+/// the host as well, so a recording made through a proxy or mock server replays for the real API.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FixtureRequest {
+    pub method: String,
+    pub path: String,
+    pub body: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FixtureResponse {
+    pub status: u16,
+    /// The raw body, for streams all events as they were sent
+    pub body: String,
+}
+
+impl FixtureRequest {
+    pub fn new(request: RequestBuilder) -> Result<FixtureRequest> {
+        let request = request
+            .build()
+            .map_err(|error| LlmError::Network(format!("Could not build the request: {error}")))?;
+        let path = match request.url().query() {
+            Some(query) => format!("{}?{query}", request.url().path()),
+            None => request.url().path().to_owned(),
+        };
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .and_then(|body| serde_json::from_slice(body).ok())
+            .unwrap_or_default();
+        Ok(FixtureRequest {
+            method: request.method().to_string(),
+            path,
+            body,
+        })
+    }
+
+    /// Like `gpt-3f2a9c1e0b7d4a65.json`, the same request always gets the same file
+    fn file_name(&self, provider: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.method);
+        hasher.update(" ");
+        hasher.update(&self.path);
+        hasher.update("\n");
+        // the keys of a parsed body are sorted, so the hash does not depend on field order
+        hasher.update(self.body.to_string());
+        let hash: String = hasher.finalize()[..8]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let provider: String = provider
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        format!("{provider}-{hash}.json")
+    }
+}
+
+/// The recorded response to `request`, as if it came from `provider`
+pub fn replay_fixture(dir: &Path, provider: &str, request: FixtureRequest) -> Result<Response> {
+    let fixture_path = dir.join(request.file_name(provider));
+    let json_string = fs::read_to_string(&fixture_path).map_err(|error| {
+        LlmError::Config(format!(
+            "No recorded {provider} response for this request at {}: {error}",
+            fixture_path.display()
+        ))
+    })?;
+    let fixture: Fixture = serde_json::from_str(&json_string).map_err(|error| {
+        LlmError::Config(format!(
+            "Could not parse the fixture {}: {error}",
+            fixture_path.display()
+        ))
+    })?;
+    to_response(fixture.response)
+}
+
+/// Saves `request` together with the response of `provider`, which has to be read completely
+/// for that and is handed back as a new response
+pub async fn record_fixture(
+    dir: &Path,
+    provider: &str,
+    request: FixtureRequest,
+    res: Response,
+) -> Result<Response> {
+    let status = res.status().as_u16();
+    let body = res.text().await.map_err(|error| {
+        LlmError::Network(format!("Failed reading {provider} response: {error}"))
+    })?;
+    let fixture_path = dir.join(request.file_name(provider));
+    let fixture = Fixture {
+        request,
+        response: FixtureResponse { status, body },
+    };
+
+    fs::create_dir_all(dir).map_err(|error| {
+        LlmError::Config(format!("Could not create the fixture directory: {error}"))
+    })?;
+    let json_string = serde_json::to_string_pretty(&fixture)
+        .map_err(|error| LlmError::Config(format!("Could not stringify fixture: {error}")))?;
+    fs::write(&fixture_path, json_string).map_err(|error| {
+        LlmError::Config(format!(
+            "Could not write the fixture {}: {error}",
+            fixture_path.display()
+        ))
+    })?;
+    to_response(fixture.response)
+}
+
+fn to_response(fixture_response: FixtureResponse) -> Result<Response> {
+    http::Response::builder()
+        .status(fixture_response.status)
+        .body(fixture_response.body)
+        .map(Response::from)
+        .map_err(|error| LlmError::Config(format!("Invalid recorded response: {error}")))
+}
@@ -1,42 +1,132 @@
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::Path;
+
+use crate::llm::error::Result;
+use crate::llm::provider::{Completion, LlmProvider, ProviderConfig};
+use crate::llm::session::{ChatMessage, Role};
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Part {
+    text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Content {
+    role: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SystemInstruction {
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    temperature: f32,
+    max_output_tokens: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GeminiBody {
+    contents: Vec<Content>,
+    generation_config: GenerationConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<SystemInstruction>,
+}
+
+const BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
 
 #[derive(Clone)]
 pub struct GEMINI {
-    config: GeminiConfig,
-    endpoint: &'static str,
+    config: ProviderConfig,
 }
 
-impl GEMINI {
-    fn read_config() -> GeminiConfig {
-        let config_path = "/etc/llm_cli_config.json";
-        let json_string =
-            fs::read_to_string(Path::new(config_path)).expect("--- Could not read config file ---");
+impl LlmProvider for GEMINI {
+    fn name(&self) -> &str {
+        "Gemini"
+    }
 
-        let json_object: serde_json::Value =
-            serde_json::from_str(&json_string).expect("--- Could not parse JSON ---");
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut ProviderConfig {
+        &mut self.config
+    }
+
+    fn build_request(&self, client: &Client, messages: &[ChatMessage]) -> RequestBuilder {
+        let contents = messages
+            .iter()
+            .map(|message| Content {
+                role: match message.role {
+                    Role::User => String::from("user"),
+                    Role::Assistant => String::from("model"),
+                },
+                parts: vec![Part {
+                    text: message.content.clone(),
+                }],
+            })
+            .collect();
+        let body = GeminiBody {
+            contents,
+            generation_config: GenerationConfig {
+                temperature: self.config.temperature,
+                max_output_tokens: self.config.max_tokens,
+            },
+            system_instruction: self.config.system_prompt().map(|system_prompt| {
+                SystemInstruction {
+                    parts: vec![Part {
+                        text: system_prompt.to_owned(),
+                    }],
+                }
+            }),
+        };
 
-        let config: GeminiConfig = serde_json::from_value(json_object["gemini"].clone())
-            .expect("--- Incorrect Gemini config in the config file ---");
+        let path = match self.config.stream {
+            true => format!(
+                "/models/{}:streamGenerateContent?alt=sse",
+                self.config.model_name
+            ),
+            false => format!("/models/{}:generateContent", self.config.model_name),
+        };
+        let endpoint = self.config.endpoint(BASE_URL, &path);
 
-        config
+        client
+            .post(endpoint)
+            .header("Content-Type", "application/json")
+            .header("x-goog-api-key", self.config.api_key.as_str())
+            .json(&body)
     }
-}
-impl Default for GEMINI {
-    fn default() -> Self {
-        let config = GEMINI::read_config();
-        GEMINI {
-            endpoint: "https://generativelanguage.googleapis.com/v1beta/models/",
-            config,
-        }
+
+    fn parse_response(&self, response: &serde_json::Value) -> Option<String> {
+        // the answer can be split into several parts, which are simply concatenated
+        let parts = response["candidates"][0]["content"]["parts"].as_array()?;
+        parts
+            .iter()
+            .map(|part| part["text"].as_str())
+            .collect::<Option<Vec<&str>>>()
+            .map(|texts| texts.concat())
     }
-}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct GeminiConfig {
-    api_key: String,
-    model_version: String,
-    max_tokens: u16,
-    temperature: f32,
+    fn parse_stream_event(&self, event: &serde_json::Value) -> Option<String> {
+        // every streamed event is a complete response holding the next piece of the answer
+        self.parse_response(event)
+    }
+
+    fn parse_metadata(&self, response: &serde_json::Value, completion: &mut Completion) {
+        completion.set_finish_reason(&response["candidates"][0]["finishReason"]);
+        completion.set_usage(
+            &response["usageMetadata"]["promptTokenCount"],
+            &response["usageMetadata"]["candidatesTokenCount"],
+        );
+    }
+}
+impl GEMINI {
+    pub fn new() -> Result<Self> {
+        let config = ProviderConfig::read("gemini", "Gemini", Some("GEMINI_API_KEY"))?;
+        Ok(GEMINI { config })
+    }
 }
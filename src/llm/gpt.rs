@@ -1,13 +1,11 @@
-use crate::llm::utils::print_response;
-use reqwest::{self, Client};
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use std::ops::Deref;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::{Duration, Instant};
-use std::{fs, sync::Arc};
-use tokio::sync::Mutex;
 
-use super::utils::get_config_path;
+use crate::llm::error::{LlmError, Result};
+use crate::llm::provider::{Completion, LlmProvider, ProviderConfig};
+use crate::llm::session::ChatMessage;
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Message {
@@ -20,156 +18,161 @@ struct GPTBody {
     max_tokens: u16,
     temperature: f32,
     messages: Vec<Message>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct GPTConfig {
-    api_key: String,
-    model_name: String,
-    max_tokens: u16,
-    temperature: f32,
+#[derive(Serialize, Deserialize, Debug)]
+struct StreamOptions {
+    include_usage: bool,
 }
 
-#[derive(Clone)]
-pub struct GPT {
-    config: GPTConfig,
-    endpoint: &'static str,
+/// The parts of a config section with `"type": "openai"` that the built-in GPT gets from code
+#[derive(Deserialize, Debug)]
+struct CompatibleConfig {
+    /// Name shown in the output, defaults to the key of the section
+    name: Option<String>,
+    base_url: String,
+    /// `bearer`, `none` or the name of a header that holds the plain key, like `api-key`
+    #[serde(default = "default_auth")]
+    auth: String,
+    api_key_env: Option<String>,
+    /// Sends `stream_options.include_usage`, which not every compatible server understands
+    #[serde(default)]
+    stream_usage: bool,
 }
 
-impl GPT {
-    fn read_config() -> GPTConfig {
-        let config_path = get_config_path();
-        let json_string =
-            fs::read_to_string(config_path).expect("--- Could not read config file ---");
-
-        let json_object: serde_json::Value =
-            serde_json::from_str(&json_string).expect("--- Could not parse JSON ---");
+fn default_auth() -> String {
+    String::from("bearer")
+}
 
-        let config: GPTConfig = serde_json::from_value(json_object["gpt"].clone())
-            .expect("--- Incorrect GPT config in the config file ---");
+#[derive(Clone)]
+enum Auth {
+    Bearer,
+    Header(String),
+    None,
+}
 
-        if config.api_key.is_empty() {
-            panic!("--- No GPT API Key provided ---")
-        }
+/// OpenAI itself, but also any other API speaking the OpenAI chat completions format
+#[derive(Clone)]
+pub struct GPT {
+    config: ProviderConfig,
+    name: String,
+    auth: Auth,
+    /// OpenAI only reports the usage of a stream when asked, other servers may reject the option
+    stream_usage: bool,
+}
 
-        config
+impl LlmProvider for GPT {
+    fn name(&self) -> &str {
+        &self.name
     }
-    pub fn parse_args(&mut self, args: Vec<String>) -> String {
-        for (i, arg) in args.iter().enumerate() {
-            if arg == "$" {
-                return args[i + 1..].join(" ");
-            }
-
-            if arg.starts_with("-model=") {
-                self.config.model_name = arg[7..].to_owned();
-                continue;
-            }
-
-            if arg.starts_with("-temp=") {
-                self.config.temperature = arg[6..]
-                    .parse::<f32>()
-                    .expect("Could not parse temp to a float");
-                continue;
-            }
 
-            if arg.starts_with("-token=") {
-                self.config.max_tokens = arg[7..]
-                    .parse::<u16>()
-                    .expect("Could not parse token to a float");
-                continue;
-            }
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
 
-            panic!("--- Found invalid argument: {arg} ---")
-        }
-        panic!("--- Missing '$' command ---")
+    fn config_mut(&mut self) -> &mut ProviderConfig {
+        &mut self.config
     }
-    pub async fn make_request(
-        &self,
-        prompt: Arc<String>,
-        request_number: Arc<AtomicUsize>,
-        lock: Arc<Mutex<()>>,
-    ) {
-        // building the body
-        let messages = vec![Message {
-            role: String::from("user"),
-            content: prompt.deref().to_owned(),
-        }];
+
+    fn build_request(&self, client: &Client, messages: &[ChatMessage]) -> RequestBuilder {
+        let system_message = self.config.system_prompt().map(|system_prompt| Message {
+            role: String::from("system"),
+            content: system_prompt.to_owned(),
+        });
+        let messages = system_message
+            .into_iter()
+            .chain(messages.iter().map(|message| Message {
+                role: message.role.as_str().to_owned(),
+                content: message.content.clone(),
+            }))
+            .collect();
         let body = GPTBody {
             temperature: self.config.temperature,
             max_tokens: self.config.max_tokens,
             model: self.config.model_name.clone(),
             messages,
+            stream: self.config.stream,
+            stream_options: (self.config.stream && self.stream_usage).then_some(StreamOptions {
+                include_usage: true,
+            }),
         };
-        let json_body = serde_json::to_string(&body).unwrap_or_else(|_| {
-            request_number.fetch_sub(1, Ordering::SeqCst);
-            panic!("--- Could not stringify GPT config to JSON ---")
-        });
 
-        // making request as well as measuring time taken
-        let client_builder = Client::builder().timeout(Duration::from_secs(120));
-        let client = client_builder.build().unwrap_or_else(|_| {
-            request_number.fetch_sub(1, Ordering::SeqCst);
-            panic!("--- Could not create GPT client ---")
-        });
-        let req_start = Instant::now();
-        let res = client
-            .post(self.endpoint)
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .body(json_body)
-            .send()
-            .await
-            .unwrap_or_else(|_| {
-                request_number.fetch_sub(1, Ordering::SeqCst);
-                panic!("--- Request to GPT endpoint failed ---")
-            });
-
-        // signal to loading loop to stop/decrement
-        request_number.fetch_sub(1, Ordering::SeqCst);
-
-        // if req successful
-        if res.status().is_success() {
-            let response_text = res.text().await.unwrap_or_else(|_| {
-                request_number.fetch_sub(1, Ordering::SeqCst);
-                panic!("--- Failed parsing GPT response message ---")
-            });
-            let parsed_response_text: serde_json::Value = serde_json::from_str(&response_text)
-                .unwrap_or_else(|_| {
-                    request_number.fetch_sub(1, Ordering::SeqCst);
-                    panic!("--- Failed parsing GPT response message ---")
-                });
-            let response_text: String = serde_json::from_value(
-                parsed_response_text["choices"][0]["message"]["content"].clone(),
-            )
-            .unwrap_or_else(|_| {
-                request_number.fetch_sub(1, Ordering::SeqCst);
-                panic!("--- Malformed GPT JSON response ---")
-            });
-
-            let req_time = req_start.elapsed();
-            let _lock = lock.lock().await;
-            print_response(&response_text, req_time.as_secs_f64(), 55, "GPT");
-            return;
-        }
-
-        // if something went wrong
-        let _lock = lock.lock().await;
-        panic!(
-            "--- Request to GPT failed with: ---\nStatus Code: {}\nError Message: {}",
-            res.status(),
-            res.text().await.unwrap_or_else(|_| {
-                request_number.fetch_sub(1, Ordering::SeqCst);
-                panic!("--- Failed parsing GPT response message ---")
-            })
-        )
+        let request = client
+            .post(self.config.endpoint(OPENAI_BASE_URL, "/chat/completions"))
+            .header("Content-Type", "application/json");
+        let request = match &self.auth {
+            Auth::Bearer => {
+                request.header("Authorization", format!("Bearer {}", self.config.api_key))
+            }
+            Auth::Header(header) => request.header(header.as_str(), self.config.api_key.as_str()),
+            Auth::None => request,
+        };
+        request.json(&body)
+    }
+
+    fn parse_response(&self, response: &serde_json::Value) -> Option<String> {
+        serde_json::from_value(response["choices"][0]["message"]["content"].clone()).ok()
+    }
+
+    fn parse_stream_event(&self, event: &serde_json::Value) -> Option<String> {
+        event["choices"][0]["delta"]["content"]
+            .as_str()
+            .map(str::to_owned)
+    }
+
+    fn parse_metadata(&self, response: &serde_json::Value, completion: &mut Completion) {
+        completion.set_finish_reason(&response["choices"][0]["finish_reason"]);
+        completion.set_usage(
+            &response["usage"]["prompt_tokens"],
+            &response["usage"]["completion_tokens"],
+        );
     }
 }
-impl Default for GPT {
-    fn default() -> Self {
-        let config = GPT::read_config();
-        GPT {
-            endpoint: "https://api.openai.com/v1/chat/completions",
+impl GPT {
+    pub fn new() -> Result<Self> {
+        let config = ProviderConfig::read("gpt", "GPT", Some("OPENAI_API_KEY"))?;
+        Ok(GPT {
+            name: String::from("GPT"),
+            auth: Auth::Bearer,
+            stream_usage: true,
+            config,
+        })
+    }
+
+    /// Provider defined by the config section `key`, like Groq, OpenRouter or a local vLLM
+    pub fn openai_compatible(key: &str, section: &serde_json::Value) -> Result<Self> {
+        let compatible_config: CompatibleConfig =
+            serde_json::from_value(section.clone()).map_err(|error| {
+                LlmError::Config(format!(
+                    "Incorrect {key} config in the config file: {error}"
+                ))
+            })?;
+        let name = compatible_config.name.unwrap_or_else(|| key.to_owned());
+        let auth = match compatible_config.auth.as_str() {
+            "bearer" => Auth::Bearer,
+            "none" => Auth::None,
+            header => Auth::Header(header.to_owned()),
+        };
+
+        // e.g. GROQ_API_KEY for a section called "groq"
+        let api_key_env = compatible_config
+            .api_key_env
+            .unwrap_or_else(|| format!("{}_API_KEY", key.to_uppercase().replace('-', "_")));
+        let api_key_env = match auth {
+            Auth::None => None,
+            _ => Some(api_key_env.as_str()),
+        };
+        let mut config = ProviderConfig::read(key, &name, api_key_env)?;
+        config.base_url = Some(compatible_config.base_url);
+
+        Ok(GPT {
+            name,
+            auth,
+            stream_usage: compatible_config.stream_usage,
             config,
-        }
+        })
     }
 }
@@ -0,0 +1,62 @@
+use std::fs;
+use std::io::{stdin, IsTerminal, Read};
+
+use crate::llm::cli::PromptArgs;
+use crate::llm::error::{LlmError, Result};
+
+/// The full text sent to the model and a short version of it that is echoed to the user
+pub struct Prompt {
+    pub text: String,
+    pub display: String,
+}
+
+/// Combines the prompt after `$` with piped stdin (if `read_stdin` is set) and the contents
+/// of the `-file=` paths, each file below a header with its name.
+/// Returns `None` if there is nothing to send at all.
+pub fn read_prompt(prompt_args: &PromptArgs, read_stdin: bool) -> Result<Option<Prompt>> {
+    let mut parts = Vec::new();
+    let mut attachments = Vec::new();
+
+    let prompt = prompt_args
+        .prompt
+        .as_deref()
+        .map(str::trim)
+        .filter(|prompt| !prompt.is_empty());
+    if let Some(prompt) = prompt {
+        parts.push(prompt.to_owned());
+    }
+
+    if read_stdin && !stdin().is_terminal() {
+        let mut piped_input = String::new();
+        stdin().read_to_string(&mut piped_input).map_err(|error| {
+            LlmError::Usage(format!("Could not read the prompt from stdin: {error}"))
+        })?;
+        // stdin of cron jobs and the like is not a terminal either, but empty
+        if !piped_input.trim().is_empty() {
+            parts.push(piped_input.trim_end().to_owned());
+            attachments.push(String::from("stdin"));
+        }
+    }
+
+    for file in &prompt_args.files {
+        let content = fs::read_to_string(file)
+            .map_err(|error| LlmError::Usage(format!("Could not read file {file}: {error}")))?;
+        parts.push(format!("File: {file}\n```\n{}\n```", content.trim_end()));
+        attachments.push(file.clone());
+    }
+
+    if parts.is_empty() {
+        return Ok(None);
+    }
+
+    // files and logs can be long, so only their names are echoed
+    let display = match (prompt, attachments.is_empty()) {
+        (Some(prompt), true) => prompt.to_owned(),
+        (Some(prompt), false) => format!("{prompt} (attached: {})", attachments.join(", ")),
+        (None, _) => format!("(attached: {})", attachments.join(", ")),
+    };
+    Ok(Some(Prompt {
+        text: parts.join("\n\n"),
+        display,
+    }))
+}
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::llm::error::{LlmError, Result};
+
+/// One finished request, stored as a line of JSON in the usage ledger
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LedgerEntry {
+    pub timestamp: DateTime<Utc>,
+    pub provider: String,
+    pub model: String,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    /// Estimated cost in USD
+    pub cost: Option<f64>,
+    /// Seconds until the answer was complete or the request failed
+    pub latency: f64,
+    pub success: bool,
+}
+
+/// Appends the entry to the ledger, creating it on the first request
+pub fn append_entry(entry: &LedgerEntry) -> Result<()> {
+    let ledger_path = get_ledger_path()?;
+    if let Some(ledger_dir) = ledger_path.parent() {
+        fs::create_dir_all(ledger_dir).map_err(|error| {
+            LlmError::Config(format!("Could not create the data directory: {error}"))
+        })?;
+    }
+
+    let mut line = serde_json::to_string(entry)
+        .map_err(|error| LlmError::Config(format!("Could not stringify ledger entry: {error}")))?;
+    line.push('\n');
+
+    // parallel requests append at the same time, a single write keeps their lines whole
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&ledger_path)
+        .and_then(|mut ledger| ledger.write_all(line.as_bytes()))
+        .map_err(|error| {
+            LlmError::Config(format!(
+                "Could not write the usage ledger {}: {error}",
+                ledger_path.display()
+            ))
+        })
+}
+
+/// All entries of the ledger, lines that cannot be parsed are skipped
+pub fn read_entries() -> Result<Vec<LedgerEntry>> {
+    let ledger_path = get_ledger_path()?;
+    if !ledger_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let json_lines = fs::read_to_string(&ledger_path).map_err(|error| {
+        LlmError::Config(format!(
+            "Could not read the usage ledger {}: {error}",
+            ledger_path.display()
+        ))
+    })?;
+    Ok(json_lines
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+fn get_ledger_path() -> Result<PathBuf> {
+    let mut ledger_path = dirs::data_dir()
+        .ok_or_else(|| LlmError::Config(String::from("Could not find the user data directory")))?;
+    ledger_path.push("llm-cli");
+    ledger_path.push("usage.jsonl");
+    Ok(ledger_path)
+}
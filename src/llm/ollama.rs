@@ -0,0 +1,116 @@
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use crate::llm::error::Result;
+use crate::llm::provider::{Completion, LlmProvider, ProviderConfig};
+use crate::llm::session::ChatMessage;
+use crate::llm::stream::StreamFormat;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Options {
+    temperature: f32,
+    num_predict: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OllamaBody {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    options: Options,
+}
+
+const DEFAULT_HOST: &str = "http://localhost:11434";
+
+/// Local models served by Ollama, no API key needed
+#[derive(Clone)]
+pub struct OLLAMA {
+    config: ProviderConfig,
+    /// Address of the Ollama server, `base_url` of the config takes precedence
+    host: String,
+}
+
+impl LlmProvider for OLLAMA {
+    fn name(&self) -> &str {
+        "Ollama"
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut ProviderConfig {
+        &mut self.config
+    }
+
+    fn build_request(&self, client: &Client, messages: &[ChatMessage]) -> RequestBuilder {
+        let system_message = self.config.system_prompt().map(|system_prompt| Message {
+            role: String::from("system"),
+            content: system_prompt.to_owned(),
+        });
+        let messages = system_message
+            .into_iter()
+            .chain(messages.iter().map(|message| Message {
+                role: message.role.as_str().to_owned(),
+                content: message.content.clone(),
+            }))
+            .collect();
+        let body = OllamaBody {
+            model: self.config.model_name.clone(),
+            messages,
+            stream: self.config.stream,
+            options: Options {
+                temperature: self.config.temperature,
+                num_predict: self.config.max_tokens,
+            },
+        };
+
+        client
+            .post(self.config.endpoint(&self.host, "/api/chat"))
+            .header("Content-Type", "application/json")
+            .json(&body)
+    }
+
+    fn parse_response(&self, response: &serde_json::Value) -> Option<String> {
+        serde_json::from_value(response["message"]["content"].clone()).ok()
+    }
+
+    fn parse_stream_event(&self, event: &serde_json::Value) -> Option<String> {
+        // every streamed line has the same shape as the full response
+        self.parse_response(event)
+    }
+
+    fn parse_metadata(&self, response: &serde_json::Value, completion: &mut Completion) {
+        completion.set_finish_reason(&response["done_reason"]);
+        completion.set_usage(&response["prompt_eval_count"], &response["eval_count"]);
+    }
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Ndjson
+    }
+}
+impl OLLAMA {
+    pub fn new() -> Result<Self> {
+        let config = ProviderConfig::read("ollama", "Ollama", None)?;
+
+        // OLLAMA_HOST is what the Ollama CLI itself uses, it may come without a scheme
+        let host = env::var("OLLAMA_HOST")
+            .ok()
+            .filter(|host| !host.is_empty())
+            .or_else(|| config.host.clone())
+            .unwrap_or_else(|| String::from(DEFAULT_HOST));
+        let host = match host.contains("://") {
+            true => host,
+            false => format!("http://{host}"),
+        };
+
+        Ok(OLLAMA { config, host })
+    }
+}
@@ -0,0 +1,96 @@
+use serde::Serialize;
+
+use crate::llm::error::{LlmError, Result};
+use crate::llm::provider::{Completion, Usage};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Decorated answers for humans
+    #[default]
+    Text,
+    /// One JSON document once all answers are there
+    Json,
+    /// One JSON line per answer as soon as it is there
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub fn parse(format: &str) -> Result<OutputFormat> {
+        match format {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(LlmError::Usage(format!(
+                "Invalid format '{format}', choose between 'text', 'json' and 'ndjson'"
+            ))),
+        }
+    }
+}
+
+/// Machine readable result of one model
+#[derive(Serialize, Debug, Clone)]
+pub struct ResponseRecord {
+    pub provider: String,
+    pub model: String,
+    pub prompt: String,
+    pub response: String,
+    /// Seconds from sending the request until the answer was complete
+    pub latency: f64,
+    pub finish_reason: Option<String>,
+    pub usage: Usage,
+    /// Estimated cost in USD
+    pub cost: Option<f64>,
+    /// Answered from the response cache, latency and usage are the ones of the original request
+    pub cached: bool,
+}
+
+impl ResponseRecord {
+    pub fn new(
+        provider: &str,
+        model: &str,
+        prompt: &str,
+        completion: &Completion,
+    ) -> ResponseRecord {
+        ResponseRecord {
+            provider: provider.to_owned(),
+            model: model.to_owned(),
+            prompt: prompt.to_owned(),
+            response: completion.text.clone(),
+            latency: completion.latency,
+            finish_reason: completion.finish_reason.clone(),
+            usage: completion.usage,
+            cost: completion.cost,
+            cached: completion.cached,
+        }
+    }
+}
+
+/// Machine readable failure of one model, written where its `ResponseRecord` would be
+#[derive(Serialize, Debug, Clone)]
+pub struct ErrorRecord {
+    pub provider: String,
+    pub model: String,
+    pub prompt: String,
+    pub error: String,
+}
+
+/// Outcome of one of several models, both kinds go into the same output
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ModelRecord {
+    Answer(ResponseRecord),
+    Failure(ErrorRecord),
+}
+
+/// Prints a record, or a list of them, as one pretty JSON document
+pub fn print_json<T: Serialize + ?Sized>(records: &T) {
+    // the records only hold strings and numbers, serializing them cannot fail
+    println!(
+        "{}",
+        serde_json::to_string_pretty(records).unwrap_or_default()
+    );
+}
+
+pub fn print_ndjson<T: Serialize>(record: &T) {
+    println!("{}", serde_json::to_string(record).unwrap_or_default());
+}
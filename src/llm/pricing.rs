@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::llm::config::read_config;
+use crate::llm::provider::Usage;
+
+/// Price of a model in USD per million tokens
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// List prices of the hosted models, matched by the longest prefix of the model name.
+/// They change now and then, so the `pricing` section of the config file takes precedence.
+const BUILTIN_PRICES: &[(&str, Price)] = &[
+    (
+        "gpt-4o-mini",
+        Price {
+            input: 0.15,
+            output: 0.6,
+        },
+    ),
+    (
+        "gpt-4o",
+        Price {
+            input: 2.5,
+            output: 10.0,
+        },
+    ),
+    (
+        "gpt-4-turbo",
+        Price {
+            input: 10.0,
+            output: 30.0,
+        },
+    ),
+    (
+        "gpt-4-0125-preview",
+        Price {
+            input: 10.0,
+            output: 30.0,
+        },
+    ),
+    (
+        "gpt-4-1106-preview",
+        Price {
+            input: 10.0,
+            output: 30.0,
+        },
+    ),
+    (
+        "gpt-4",
+        Price {
+            input: 30.0,
+            output: 60.0,
+        },
+    ),
+    (
+        "gpt-3.5-turbo",
+        Price {
+            input: 0.5,
+            output: 1.5,
+        },
+    ),
+    (
+        "o1-mini",
+        Price {
+            input: 3.0,
+            output: 12.0,
+        },
+    ),
+    (
+        "o1",
+        Price {
+            input: 15.0,
+            output: 60.0,
+        },
+    ),
+    (
+        "claude-3-5-sonnet",
+        Price {
+            input: 3.0,
+            output: 15.0,
+        },
+    ),
+    (
+        "claude-3-5-haiku",
+        Price {
+            input: 0.8,
+            output: 4.0,
+        },
+    ),
+    (
+        "claude-3-opus",
+        Price {
+            input: 15.0,
+            output: 75.0,
+        },
+    ),
+    (
+        "claude-3-sonnet",
+        Price {
+            input: 3.0,
+            output: 15.0,
+        },
+    ),
+    (
+        "claude-3-haiku",
+        Price {
+            input: 0.25,
+            output: 1.25,
+        },
+    ),
+    (
+        "mistral-large",
+        Price {
+            input: 2.0,
+            output: 6.0,
+        },
+    ),
+    (
+        "mistral-medium",
+        Price {
+            input: 2.7,
+            output: 8.1,
+        },
+    ),
+    (
+        "mistral-small",
+        Price {
+            input: 0.2,
+            output: 0.6,
+        },
+    ),
+    (
+        "open-mistral-7b",
+        Price {
+            input: 0.25,
+            output: 0.25,
+        },
+    ),
+    (
+        "open-mixtral-8x7b",
+        Price {
+            input: 0.7,
+            output: 0.7,
+        },
+    ),
+    (
+        "open-mixtral-8x22b",
+        Price {
+            input: 2.0,
+            output: 6.0,
+        },
+    ),
+    (
+        "gemini-1.5-pro",
+        Price {
+            input: 1.25,
+            output: 5.0,
+        },
+    ),
+    (
+        "gemini-1.5-flash",
+        Price {
+            input: 0.075,
+            output: 0.3,
+        },
+    ),
+    (
+        "gemini-1.0-pro",
+        Price {
+            input: 0.5,
+            output: 1.5,
+        },
+    ),
+    (
+        "gemini-pro",
+        Price {
+            input: 0.5,
+            output: 1.5,
+        },
+    ),
+];
+
+/// Price of `model_name`, from the `pricing` section of the config file or the built-in table
+pub fn get_price(model_name: &str) -> Option<Price> {
+    // a broken pricing section only costs us the cost estimate, not the answer
+    let config_prices: HashMap<String, Price> = read_config()
+        .ok()
+        .and_then(|config| serde_json::from_value(config["pricing"].clone()).ok())
+        .unwrap_or_default();
+
+    find_price(
+        config_prices
+            .iter()
+            .map(|(model, price)| (model.as_str(), *price)),
+        model_name,
+    )
+    .or_else(|| find_price(BUILTIN_PRICES.iter().copied(), model_name))
+}
+
+/// The entry with the longest prefix of `model_name`, so `gpt-4o-mini-2024-07-18`
+/// gets the price of `gpt-4o-mini` and not the one of `gpt-4o`
+fn find_price<'a>(
+    prices: impl Iterator<Item = (&'a str, Price)>,
+    model_name: &str,
+) -> Option<Price> {
+    prices
+        .filter(|(model, _)| {
+            model_name
+                .strip_prefix(model)
+                .is_some_and(is_version_suffix)
+        })
+        .max_by_key(|(model, _)| model.len())
+        .map(|(_, price)| price)
+}
+
+/// Whether the rest of a model name only picks a version of the listed model, a date like
+/// `-2024-07-18`, `-20240229` or `-0613`, `-latest` or `-preview`. Anything else like `-pro`
+/// in `o1-pro`, `.1` in `gpt-4.1` or `-32k` in `gpt-4-32k` is another model.
+fn is_version_suffix(suffix: &str) -> bool {
+    let Some(version) = suffix.strip_prefix('-') else {
+        return suffix.is_empty();
+    };
+    let is_digits =
+        |part: &str, len: usize| part.len() == len && part.chars().all(|c| c.is_ascii_digit());
+    match version.split('-').collect::<Vec<_>>().as_slice() {
+        ["latest"] | ["preview"] => true,
+        [date] => is_digits(date, 8) || is_digits(date, 4),
+        [year, month, day] => is_digits(year, 4) && is_digits(month, 2) && is_digits(day, 2),
+        _ => false,
+    }
+}
+
+/// Estimated cost in USD, `None` if the price or the token counts are unknown
+pub fn get_cost(model_name: &str, usage: &Usage) -> Option<f64> {
+    let price = get_price(model_name)?;
+    let prompt_tokens = usage.prompt_tokens?;
+    let completion_tokens = usage.completion_tokens?;
+    Some(
+        (prompt_tokens as f64 * price.input + completion_tokens as f64 * price.output)
+            / 1_000_000.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin_price(model_name: &str) -> Option<Price> {
+        find_price(BUILTIN_PRICES.iter().copied(), model_name)
+    }
+
+    #[test]
+    fn matches_versions_of_listed_models() {
+        let gpt_4o_mini = builtin_price("gpt-4o-mini").unwrap();
+        assert_eq!(builtin_price("gpt-4o-mini-2024-07-18"), Some(gpt_4o_mini));
+        assert_eq!(
+            builtin_price("claude-3-5-haiku-latest"),
+            builtin_price("claude-3-5-haiku")
+        );
+        assert_eq!(builtin_price("gpt-4-0613"), builtin_price("gpt-4"));
+        assert_eq!(
+            builtin_price("claude-3-opus-20240229"),
+            builtin_price("claude-3-opus")
+        );
+        assert_eq!(
+            builtin_price("gpt-4-turbo-preview"),
+            builtin_price("gpt-4-turbo")
+        );
+    }
+
+    #[test]
+    fn does_not_price_unlisted_models_like_shorter_names() {
+        assert_eq!(builtin_price("gpt-4.1"), None);
+        assert_eq!(builtin_price("o1-pro"), None);
+        assert_eq!(builtin_price("gpt-4o-audio-preview"), None);
+        assert_eq!(builtin_price("gpt-4-32k"), None);
+        assert_eq!(builtin_price("gemini-1.5-flash-8b"), None);
+    }
+}
@@ -0,0 +1,630 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io::{stdout, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::llm::budget::{check_budget, estimate_cost};
+use crate::llm::cache::{CacheMode, ResponseCache};
+use crate::llm::claude::CLAUDE;
+use crate::llm::client::get_client;
+use crate::llm::config::{read_config, resolve_api_key};
+use crate::llm::error::{LlmError, Result};
+use crate::llm::fixture::{record_fixture, replay_fixture, FixtureMode, FixtureRequest};
+use crate::llm::gemini::GEMINI;
+use crate::llm::gpt::GPT;
+use crate::llm::ledger::{append_entry, LedgerEntry};
+use crate::llm::mistral::MISTRAL;
+use crate::llm::ollama::OLLAMA;
+use crate::llm::output::{print_ndjson, OutputFormat, ResponseRecord};
+use crate::llm::pricing::get_cost;
+use crate::llm::retry::{get_retry_delay, is_retryable_status};
+use crate::llm::session::ChatMessage;
+use crate::llm::stream::{StreamEvent, StreamFormat, StreamParser};
+use crate::llm::utils::{
+    print_prompt, print_response, print_response_footer, print_response_header, show_progress,
+    ModelStatus, PendingRequest, RequestState,
+};
+
+/// Token counts as reported by the provider, not every provider reports both
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+}
+
+/// A complete answer together with what the provider told us about it
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Completion {
+    pub text: String,
+    /// Why the model stopped, in the words of the provider (`stop`, `end_turn`, `length`, ...)
+    pub finish_reason: Option<String>,
+    pub usage: Usage,
+    /// Seconds from sending the request until the answer was complete
+    pub latency: f64,
+    /// Estimated cost in USD, if the price of the model and the token counts are known
+    pub cost: Option<f64>,
+    /// Served from the response cache instead of the provider
+    #[serde(skip)]
+    pub cached: bool,
+}
+
+impl Completion {
+    /// Takes the finish reason if the value holds one, so later stream events can fill it in
+    pub fn set_finish_reason(&mut self, finish_reason: &serde_json::Value) {
+        if let Some(finish_reason) = finish_reason.as_str() {
+            self.finish_reason = Some(finish_reason.to_owned());
+        }
+    }
+
+    /// Takes the token counts that are present and keeps the others
+    pub fn set_usage(
+        &mut self,
+        prompt_tokens: &serde_json::Value,
+        completion_tokens: &serde_json::Value,
+    ) {
+        if let Some(prompt_tokens) = prompt_tokens.as_u64() {
+            self.usage.prompt_tokens = Some(prompt_tokens as u32);
+        }
+        if let Some(completion_tokens) = completion_tokens.as_u64() {
+            self.usage.completion_tokens = Some(completion_tokens as u32);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderConfig {
+    #[serde(default)]
+    pub api_key: String,
+    /// Command printing the API key, e.g. `pass show openai`
+    #[serde(default, skip_serializing)]
+    pub api_key_cmd: Option<String>,
+    pub model_name: String,
+    pub max_tokens: u16,
+    pub temperature: f32,
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    /// Instruction sent ahead of the conversation, like "answer in one sentence"
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Shown instead of the provider name, set when several models of one provider are compared
+    #[serde(skip)]
+    pub label: Option<String>,
+    /// How often a request is repeated on rate limits, server and network errors
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Delay before the first retry, it doubles with every further attempt
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    /// Seconds to wait for the connection to the provider
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Seconds to wait for the answer or, while streaming, for the next part of it
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// Replaces the API address of a built-in provider, e.g. for a proxy or a mock server
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Address of a local server like Ollama, which may come without a scheme
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_delay_ms() -> u64 {
+    1000
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_read_timeout_secs() -> u64 {
+    120
+}
+
+impl ProviderConfig {
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout_secs)
+    }
+
+    /// `path` below `base_url` if it is set, otherwise below the provider's own `default_base_url`
+    pub fn endpoint(&self, default_base_url: &str, path: &str) -> String {
+        let base_url = self.base_url.as_deref().unwrap_or(default_base_url);
+        format!("{}{path}", base_url.trim_end_matches('/'))
+    }
+
+    /// Reads the section `key` of the merged config files, `label` is used in error messages.
+    /// The API key is taken from `api_key_env`, `api_key_cmd` or `api_key`, in this order,
+    /// providers without `api_key_env` do not need a key at all.
+    pub fn read(key: &str, label: &str, api_key_env: Option<&str>) -> Result<ProviderConfig> {
+        let json_object = read_config()?;
+        let mut config: ProviderConfig =
+            serde_json::from_value(json_object[key].clone()).map_err(|error| {
+                LlmError::Config(format!(
+                    "Incorrect {label} config in the config file: {error}"
+                ))
+            })?;
+
+        let Some(api_key_env) = api_key_env else {
+            return Ok(config);
+        };
+        config.api_key = resolve_api_key(
+            label,
+            api_key_env,
+            &config.api_key,
+            config.api_key_cmd.as_deref(),
+        )?;
+        if config.api_key.is_empty() {
+            return Err(LlmError::Auth(format!(
+                "No {label} API Key provided, set {api_key_env}, api_key_cmd or api_key"
+            )));
+        }
+
+        Ok(config)
+    }
+
+    /// The system prompt, unless it is missing or was cleared with an empty `-system=`
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.system_prompt
+            .as_deref()
+            .filter(|system_prompt| !system_prompt.trim().is_empty())
+    }
+}
+
+/// A backend that can answer a prompt.
+///
+/// Implementors only describe how a request is built and how the answer is pulled
+/// out of the response, sending it and printing the result is shared.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Name used in the loading message and the response header
+    fn name(&self) -> &str;
+
+    fn config(&self) -> &ProviderConfig;
+
+    /// Name of this very model in headers, notes and errors, the provider name by default
+    fn label(&self) -> &str {
+        self.config().label.as_deref().unwrap_or(self.name())
+    }
+
+    fn config_mut(&mut self) -> &mut ProviderConfig;
+
+    /// Builds the full request (endpoint, headers and body) for the given conversation,
+    /// asking for a streamed answer if `config().stream` is set
+    fn build_request(&self, client: &Client, messages: &[ChatMessage]) -> RequestBuilder;
+
+    /// Extracts the answer from the parsed JSON response body
+    fn parse_response(&self, response: &serde_json::Value) -> Option<String>;
+
+    /// Extracts the text delta from one parsed stream event, events without text yield `None`
+    fn parse_stream_event(&self, event: &serde_json::Value) -> Option<String>;
+
+    /// Picks up the finish reason and token usage from a response or stream event,
+    /// streams spread them over several events so only present values should be set
+    fn parse_metadata(&self, response: &serde_json::Value, completion: &mut Completion);
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Sse
+    }
+
+    /// Sets one of the `cli::MODEL_PARAMS`, like `temp` for `-temp=0.5` or `gpt[temp=0.5]`
+    fn set_param(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "model" => self.config_mut().model_name = value.to_owned(),
+            "temp" => {
+                let temperature = value.parse::<f32>().map_err(|_| {
+                    LlmError::Usage(format!("Could not parse temp '{value}' to a float"))
+                })?;
+                if !(0.0..=2.0).contains(&temperature) {
+                    return Err(LlmError::Usage(format!(
+                        "temp '{value}' is out of range, choose between 0 and 2"
+                    )));
+                }
+                self.config_mut().temperature = temperature;
+            }
+            "token" => {
+                self.config_mut().max_tokens = value
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|max_tokens| *max_tokens > 0)
+                    .ok_or_else(|| {
+                        LlmError::Usage(format!(
+                            "Could not parse token '{value}' to an integer between 1 and {}",
+                            u16::MAX
+                        ))
+                    })?
+            }
+            "system" => self.config_mut().system_prompt = Some(value.to_owned()),
+            // the command line only lets known names through
+            _ => return Err(LlmError::Usage(format!("Unknown parameter '{name}'"))),
+        }
+        Ok(())
+    }
+
+    /// Sends the request, trying again on rate limits, server and transport errors
+    /// as often as `config().max_retries` allows
+    async fn send_with_retries(
+        &self,
+        client: &Client,
+        messages: &[ChatMessage],
+        pending_request: &PendingRequest,
+    ) -> Result<Response> {
+        let name = self.label();
+        let max_retries = self.config().max_retries;
+        let base_delay = Duration::from_millis(self.config().retry_delay_ms);
+
+        let mut attempt = 0;
+        loop {
+            let res = tokio::time::timeout(
+                self.config().read_timeout(),
+                self.build_request(client, messages).send(),
+            )
+            .await;
+            let retry_delay =
+                match &res {
+                    Ok(Ok(res)) if is_retryable_status(res.status().as_u16()) => Some(
+                        get_retry_delay(Some(res.headers()), attempt + 1, base_delay),
+                    ),
+                    // a request that could not even be built will not work the next time either
+                    Ok(Err(error)) if !error.is_builder() => {
+                        Some(get_retry_delay(None, attempt + 1, base_delay))
+                    }
+                    Err(_) => Some(get_retry_delay(None, attempt + 1, base_delay)),
+                    _ => None,
+                };
+            let Some(retry_delay) = retry_delay.filter(|_| attempt < max_retries) else {
+                return match res {
+                    Ok(res) => res.map_err(|error| {
+                        LlmError::Network(format!("Request to {name} endpoint failed: {error}"))
+                    }),
+                    Err(_) => Err(LlmError::Network(format!(
+                        "{name} did not answer within {} seconds",
+                        self.config().read_timeout_secs
+                    ))),
+                };
+            };
+
+            attempt += 1;
+            pending_request.set_status(ModelStatus::Retrying {
+                attempt,
+                max_retries,
+            });
+            drop(res);
+            tokio::time::sleep(retry_delay).await;
+        }
+    }
+
+    /// Sends the request with retries, or answers it from fixtures if `LLM_CLI_REPLAY` is set.
+    /// With `LLM_CLI_RECORD` the response is saved as a fixture on the way.
+    async fn send_request(
+        &self,
+        messages: &[ChatMessage],
+        pending_request: &PendingRequest,
+    ) -> Result<Response> {
+        let fixture_mode = FixtureMode::from_env();
+        if let Some(FixtureMode::Replay(dir)) = &fixture_mode {
+            let request = FixtureRequest::new(self.build_request(&Client::new(), messages))?;
+            return replay_fixture(dir, self.name(), request);
+        }
+
+        let client = get_client(self.config())?;
+        let res = self
+            .send_with_retries(&client, messages, pending_request)
+            .await?;
+
+        match &fixture_mode {
+            Some(FixtureMode::Record(dir)) => {
+                let request = FixtureRequest::new(self.build_request(&client, messages))?;
+                record_fixture(dir, self.name(), request, res).await
+            }
+            _ => Ok(res),
+        }
+    }
+
+    /// Key of the conversation in the response cache, `None` without a cache
+    fn cache_key(&self, messages: &[ChatMessage], cache: Option<&ResponseCache>) -> Option<String> {
+        cache?;
+        let client = get_client(self.config()).ok()?;
+        ResponseCache::key(self.build_request(&client, messages))
+    }
+
+    /// Whether `make_request` will answer the conversation from the cache, such requests
+    /// cost nothing and are left out of the budget
+    fn is_cached(&self, messages: &[ChatMessage], cache: Option<&ResponseCache>) -> bool {
+        let cache_key = self.cache_key(messages, cache);
+        matches!((cache, cache_key), (Some(cache), Some(cache_key)) if cache.get(&cache_key).is_some())
+    }
+
+    /// Sends the conversation like `request_completion` and records the outcome in the usage
+    /// ledger. With the cache enabled a stored answer to the same request is used instead.
+    async fn make_request(
+        &self,
+        messages: Arc<Vec<ChatMessage>>,
+        state: RequestState,
+        model_index: usize,
+        live_output: bool,
+    ) -> Result<Completion> {
+        let cache_key = self.cache_key(&messages, state.cache.as_ref());
+        if let (Some(cache), Some(cache_key)) = (&state.cache, &cache_key) {
+            if let Some(completion) = cache.get(cache_key) {
+                state.pending_request(model_index).finish();
+                self.print_completion(&messages, &completion, &state).await;
+                return Ok(completion);
+            }
+        }
+
+        let req_start = Instant::now();
+        let completion = self
+            .request_completion(messages, state.clone(), model_index, live_output)
+            .await;
+
+        let (usage, cost) = match &completion {
+            Ok(completion) => (completion.usage, completion.cost),
+            Err(_) => (Usage::default(), None),
+        };
+        let ledger_entry = LedgerEntry {
+            timestamp: Utc::now(),
+            provider: self.name().to_owned(),
+            model: self.config().model_name.clone(),
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            cost,
+            latency: req_start.elapsed().as_secs_f64(),
+            success: completion.is_ok(),
+        };
+        // losing track of the spend is no reason to lose the answer
+        if let Err(error) = append_entry(&ledger_entry) {
+            let _lock = state.lock_output().await;
+            eprintln!("--- {error} ---\n");
+        }
+
+        if let (Some(cache), Some(cache_key), Ok(completion)) =
+            (&state.cache, &cache_key, &completion)
+        {
+            if let Err(error) = cache.put(cache_key, completion) {
+                let _lock = state.lock_output().await;
+                eprintln!("--- {error} ---\n");
+            }
+        }
+        completion
+    }
+
+    /// Sends the conversation, prints the answer in the format of `state` and returns it.
+    /// `model_index` is the position of this model in the progress list of `state`.
+    ///
+    /// With `live_output` streamed tokens are printed as they arrive, otherwise the
+    /// answer is printed in one piece once it is complete. JSON output is left to the
+    /// caller, as it has to wait for all models.
+    async fn request_completion(
+        &self,
+        messages: Arc<Vec<ChatMessage>>,
+        state: RequestState,
+        model_index: usize,
+        live_output: bool,
+    ) -> Result<Completion> {
+        let name = self.label();
+        let live_output = live_output && state.format == OutputFormat::Text;
+        // marks the model as failed on every early return
+        let pending_request = state.pending_request(model_index);
+
+        // making request as well as measuring time taken
+        let req_start = Instant::now();
+        let mut res = self.send_request(&messages, &pending_request).await?;
+
+        // if something went wrong
+        let status = res.status();
+        if !status.is_success() {
+            let message = read_with_timeout(self.config(), res.text())
+                .await
+                .unwrap_or_default();
+            return Err(LlmError::from_status(name, status.as_u16(), message));
+        }
+        pending_request.set_status(ModelStatus::Streaming);
+
+        let mut completion = Completion::default();
+        if !self.config().stream {
+            let response_text =
+                read_with_timeout(self.config(), res.text())
+                    .await
+                    .map_err(|error| {
+                        LlmError::Network(format!("Failed reading {name} response: {error}"))
+                    })?;
+            let parsed_response_text: serde_json::Value = serde_json::from_str(&response_text)
+                .map_err(|error| {
+                    LlmError::Parse(format!("Failed parsing {name} response message: {error}"))
+                })?;
+            completion.text = self
+                .parse_response(&parsed_response_text)
+                .ok_or_else(|| LlmError::Parse(format!("Malformed {name} JSON response")))?;
+            self.parse_metadata(&parsed_response_text, &mut completion);
+            completion.latency = req_start.elapsed().as_secs_f64();
+            completion.cost = get_cost(&self.config().model_name, &completion.usage);
+
+            pending_request.finish();
+            self.print_completion(&messages, &completion, &state).await;
+            return Ok(completion);
+        }
+
+        // the lock is held while printing live, so no other output gets mixed into the answer
+        let live_lock = if live_output {
+            let live_lock = state.lock_output().await;
+            print_response_header(name, false);
+            Some(live_lock)
+        } else {
+            None
+        };
+
+        let mut parser = StreamParser::new(self.stream_format());
+        'stream: while let Some(chunk) = read_with_timeout(self.config(), res.chunk())
+            .await
+            .map_err(|error| {
+                LlmError::Network(format!(
+                    "Reading the {name} response stream failed: {error}"
+                ))
+            })?
+        {
+            for event in parser.push(&chunk) {
+                let data = match event {
+                    StreamEvent::Data(data) => data,
+                    StreamEvent::Done => break 'stream,
+                };
+                let event: serde_json::Value = serde_json::from_str(&data).map_err(|error| {
+                    LlmError::Parse(format!("Failed parsing {name} stream event: {error}"))
+                })?;
+                if !event["error"].is_null() {
+                    return Err(LlmError::from_status(
+                        name,
+                        status.as_u16(),
+                        event["error"].to_string(),
+                    ));
+                }
+
+                self.parse_metadata(&event, &mut completion);
+                let Some(delta) = self.parse_stream_event(&event) else {
+                    continue;
+                };
+                if live_output {
+                    print!("{}", delta);
+                    let _ = stdout().flush();
+                }
+                completion.text.push_str(&delta);
+            }
+        }
+
+        completion.latency = req_start.elapsed().as_secs_f64();
+        completion.cost = get_cost(&self.config().model_name, &completion.usage);
+        pending_request.finish();
+        match live_lock {
+            Some(_live_lock) => {
+                println!("\n");
+                print_response_footer(&completion);
+            }
+            None => self.print_completion(&messages, &completion, &state).await,
+        }
+        Ok(completion)
+    }
+
+    /// Prints a complete answer unless the output format waits for all models
+    async fn print_completion(
+        &self,
+        messages: &[ChatMessage],
+        completion: &Completion,
+        state: &RequestState,
+    ) {
+        let _lock = state.lock_output().await;
+        match state.format {
+            OutputFormat::Text => print_response(completion, self.label()),
+            OutputFormat::Ndjson => print_ndjson(&ResponseRecord::new(
+                self.name(),
+                &self.config().model_name,
+                last_prompt(messages),
+                completion,
+            )),
+            OutputFormat::Json => {}
+        }
+    }
+}
+
+/// Reads the next part of a response, giving up after `read_timeout_secs` without any data
+async fn read_with_timeout<T>(
+    config: &ProviderConfig,
+    read: impl Future<Output = reqwest::Result<T>>,
+) -> std::result::Result<T, String> {
+    match tokio::time::timeout(config.read_timeout(), read).await {
+        Ok(result) => result.map_err(|error| error.to_string()),
+        Err(_) => Err(format!(
+            "nothing received for {} seconds",
+            config.read_timeout_secs
+        )),
+    }
+}
+
+/// The prompt the answer belongs to, which is the last message of the conversation
+pub fn last_prompt(messages: &[ChatMessage]) -> &str {
+    messages
+        .last()
+        .map(|message| message.content.as_str())
+        .unwrap_or_default()
+}
+
+/// Asks a single model with the progress shown until the answer starts
+pub async fn ask(
+    model: &dyn LlmProvider,
+    messages: Vec<ChatMessage>,
+    prompt: String,
+    format: OutputFormat,
+    cache_mode: CacheMode,
+) -> Result<Completion> {
+    let cache = ResponseCache::open(cache_mode)?;
+    if !model.is_cached(&messages, cache.as_ref()) {
+        check_budget(estimate_cost(model, &messages))?;
+    }
+    let state = RequestState::new(&[model.label().to_owned()], format, cache);
+
+    let progress_state = state.clone();
+    print_prompt(&prompt, format);
+    let progress = tokio::spawn(async move { show_progress(progress_state).await });
+    let response = model.make_request(Arc::new(messages), state, 0, true).await;
+
+    // let the progress clean up before anybody prints an error
+    let _ = progress.await;
+    response
+}
+
+pub type ProviderConstructor = fn() -> Result<Box<dyn LlmProvider>>;
+
+/// All known providers, keyed by the name used on the command line
+static PROVIDERS: &[(&str, ProviderConstructor)] = &[
+    ("gpt", || Ok(Box::new(GPT::new()?))),
+    ("claude", || Ok(Box::new(CLAUDE::new()?))),
+    ("mistral", || Ok(Box::new(MISTRAL::new()?))),
+    ("gemini", || Ok(Box::new(GEMINI::new()?))),
+    ("ollama", || Ok(Box::new(OLLAMA::new()?))),
+];
+
+/// Built-in providers as well as the ones defined in the config file
+pub fn provider_names() -> Vec<String> {
+    let mut names: Vec<String> = PROVIDERS.iter().map(|(name, _)| name.to_string()).collect();
+    if let Ok(serde_json::Value::Object(config)) = read_config() {
+        for (name, section) in config {
+            let kind = section["type"].as_str().unwrap_or_default();
+            if CONFIG_PROVIDER_TYPES.contains(&kind) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Values of `type` in the config file that define a new provider
+const CONFIG_PROVIDER_TYPES: &[&str] = &["openai"];
+
+pub fn get_provider(name: &str) -> Result<Box<dyn LlmProvider>> {
+    if let Some((_, constructor)) = PROVIDERS
+        .iter()
+        .find(|(provider_name, _)| *provider_name == name)
+    {
+        return constructor();
+    }
+
+    // providers that are defined entirely in the config file
+    let config = read_config()?;
+    match config[name]["type"].as_str() {
+        Some("openai") => Ok(Box::new(GPT::openai_compatible(name, &config[name])?)),
+        _ => Err(LlmError::Usage(format!(
+            "Invalid model '{name}', choose between '{}' or a combination of those seperated by ','",
+            provider_names().join("', '")
+        ))),
+    }
+}
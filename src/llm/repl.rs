@@ -0,0 +1,202 @@
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::path::PathBuf;
+
+use crate::llm::cache::CacheMode;
+use crate::llm::error::{LlmError, Result};
+use crate::llm::output::OutputFormat;
+use crate::llm::provider::{ask, LlmProvider};
+use crate::llm::session::Session;
+
+const HELP: &str = "\
+Commands:
+  /model [name]   show or change the model name
+  /temp <x>       change the temperature
+  /tokens <x>     change max_tokens
+  /clear          forget the conversation so far
+  /save [name]    save the conversation as a session (and keep saving it)
+  /exit           leave the chat
+End a line with '\\' to continue your message on the next line.";
+
+/// Interactive chat with a single model, keeping the conversation across turns
+pub async fn run_repl(
+    mut model: Box<dyn LlmProvider>,
+    session: Option<Session>,
+    first_prompt: Option<String>,
+    cache_mode: CacheMode,
+) -> Result<()> {
+    let mut editor = DefaultEditor::new().map_err(|error| {
+        LlmError::Usage(format!("Could not start the interactive mode: {error}"))
+    })?;
+    let history_path = get_history_path();
+    if let Some(history_path) = &history_path {
+        // there is no history on the first start
+        let _ = editor.load_history(history_path);
+    }
+
+    // a session given with -session is saved after every answer, otherwise only after /save
+    let mut autosave = session.is_some();
+    let mut session = session.unwrap_or(Session {
+        name: String::new(),
+        messages: Vec::new(),
+    });
+
+    println!(
+        "Chatting with {} ({}), type /help for the available commands",
+        model.name(),
+        model.config().model_name
+    );
+
+    let mut pending_prompt = first_prompt;
+    loop {
+        let input = match pending_prompt.take() {
+            Some(prompt) => prompt,
+            None => match read_input(&mut editor) {
+                Some(input) => input,
+                None => break,
+            },
+        };
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        if let Some(command) = input.strip_prefix('/') {
+            match handle_command(command, model.as_mut(), &mut session, &mut autosave) {
+                CommandResult::Continue => continue,
+                CommandResult::Exit => break,
+            }
+        }
+
+        // a failed request only loses this turn, the conversation goes on
+        let messages = session.with_prompt(input);
+        let response = match ask(
+            model.as_ref(),
+            messages,
+            String::new(),
+            OutputFormat::Text,
+            cache_mode,
+        )
+        .await
+        {
+            Ok(completion) => completion.text,
+            Err(error) => {
+                eprintln!("--- {error} ---\n");
+                continue;
+            }
+        };
+        session.push_turn(input, &response);
+        if autosave {
+            if let Err(error) = session.save() {
+                eprintln!("--- {error} ---\n");
+            }
+        }
+    }
+
+    if let Some(history_path) = &history_path {
+        if let Some(history_dir) = history_path.parent() {
+            let _ = std::fs::create_dir_all(history_dir);
+        }
+        let _ = editor.save_history(history_path);
+    }
+    Ok(())
+}
+
+enum CommandResult {
+    Continue,
+    Exit,
+}
+
+fn handle_command(
+    command: &str,
+    model: &mut dyn LlmProvider,
+    session: &mut Session,
+    autosave: &mut bool,
+) -> CommandResult {
+    let (name, value) = match command.split_once(char::is_whitespace) {
+        Some((name, value)) => (name, value.trim()),
+        None => (command, ""),
+    };
+
+    match name {
+        "model" if value.is_empty() => println!("Model: {}", model.config().model_name),
+        "model" => match model.set_param("model", value) {
+            Ok(()) => println!("Model set to {}", model.config().model_name),
+            Err(error) => eprintln!("--- {error} ---"),
+        },
+        "temp" => match model.set_param("temp", value) {
+            Ok(()) => println!("Temperature set to {}", model.config().temperature),
+            Err(error) => eprintln!("--- {error} ---"),
+        },
+        "tokens" => match model.set_param("token", value) {
+            Ok(()) => println!("Max tokens set to {}", model.config().max_tokens),
+            Err(error) => eprintln!("--- {error} ---"),
+        },
+        "clear" => {
+            session.messages.clear();
+            if *autosave {
+                if let Err(error) = session.save() {
+                    eprintln!("--- {error} ---");
+                }
+            }
+            println!("Conversation cleared");
+        }
+        "save" => {
+            if !value.is_empty() {
+                session.name = value.to_owned();
+            }
+            if session.name.is_empty() {
+                println!("Usage: /save <name>");
+                return CommandResult::Continue;
+            }
+            match session.save() {
+                Ok(()) => {
+                    *autosave = true;
+                    println!("Saved session '{}'", session.name);
+                }
+                Err(error) => eprintln!("--- {error} ---"),
+            }
+        }
+        "exit" | "quit" => return CommandResult::Exit,
+        "help" => println!("{HELP}"),
+        _ => println!("Unknown command /{name}, type /help for the available commands"),
+    }
+    CommandResult::Continue
+}
+
+/// Reads one message, lines ending with '\' are joined with the next one.
+/// Returns `None` once the user wants to leave (Ctrl-D) or the input is gone.
+fn read_input(editor: &mut DefaultEditor) -> Option<String> {
+    // readline blocks, so the runtime has to move other tasks off this thread
+    tokio::task::block_in_place(|| {
+        let mut input = String::new();
+        loop {
+            let line_prompt = if input.is_empty() { "> " } else { "... " };
+            match editor.readline(line_prompt) {
+                Ok(line) => {
+                    let _ = editor.add_history_entry(line.as_str());
+                    match line.strip_suffix('\\') {
+                        Some(line) => {
+                            input.push_str(line);
+                            input.push('\n');
+                        }
+                        None => {
+                            input.push_str(&line);
+                            return Some(input);
+                        }
+                    }
+                }
+                // Ctrl-C throws away the current message
+                Err(ReadlineError::Interrupted) => return Some(String::new()),
+                Err(_) => return None,
+            }
+        }
+    })
+}
+
+fn get_history_path() -> Option<PathBuf> {
+    let mut history_path = dirs::data_dir()?;
+    history_path.push("llm-cli");
+    history_path.push("repl_history");
+    Some(history_path)
+}
@@ -0,0 +1,214 @@
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, TimeZone, Utc};
+use std::collections::BTreeMap;
+
+use crate::llm::error::{LlmError, Result};
+use crate::llm::ledger::{read_entries, LedgerEntry};
+
+#[derive(Clone, Copy, PartialEq)]
+enum GroupBy {
+    Day,
+    Model,
+    Provider,
+}
+
+impl GroupBy {
+    fn column_name(&self) -> &'static str {
+        match self {
+            GroupBy::Day => "day",
+            GroupBy::Model => "model",
+            GroupBy::Provider => "provider",
+        }
+    }
+
+    fn key(&self, entry: &LedgerEntry) -> String {
+        match self {
+            GroupBy::Day => entry
+                .timestamp
+                .with_timezone(&Local)
+                .format("%Y-%m-%d")
+                .to_string(),
+            GroupBy::Model => entry.model.clone(),
+            GroupBy::Provider => entry.provider.clone(),
+        }
+    }
+}
+
+/// Sums of all ledger entries with the same key
+#[derive(Default)]
+struct UsageSummary {
+    requests: u32,
+    failed: u32,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: f64,
+}
+
+impl UsageSummary {
+    fn add(&mut self, entry: &LedgerEntry) {
+        self.requests += 1;
+        if !entry.success {
+            self.failed += 1;
+        }
+        self.prompt_tokens += entry.prompt_tokens.unwrap_or_default() as u64;
+        self.completion_tokens += entry.completion_tokens.unwrap_or_default() as u64;
+        self.cost += entry.cost.unwrap_or_default();
+    }
+}
+
+/// `llm-cli usage`, sums up the usage ledger by day, model or provider
+pub fn run_usage_report(args: Vec<String>) -> Result<()> {
+    let mut group_by = GroupBy::Day;
+    let mut since = None;
+    let mut csv = false;
+    for arg in &args {
+        if let Some(group) = arg.strip_prefix("-by=") {
+            group_by = match group {
+                "day" => GroupBy::Day,
+                "model" => GroupBy::Model,
+                "provider" => GroupBy::Provider,
+                _ => {
+                    return Err(LlmError::Usage(format!(
+                        "Invalid grouping '{group}', choose between 'day', 'model' and 'provider'"
+                    )))
+                }
+            };
+            continue;
+        }
+
+        if let Some(date) = arg.strip_prefix("-since=") {
+            since = Some(parse_since(date)?);
+            continue;
+        }
+
+        if let Some(format) = arg.strip_prefix("-format=") {
+            csv = match format {
+                "text" => false,
+                "csv" => true,
+                _ => {
+                    return Err(LlmError::Usage(format!(
+                        "Invalid format '{format}', choose between 'text' and 'csv'"
+                    )))
+                }
+            };
+            continue;
+        }
+
+        return Err(LlmError::Usage(format!("Found invalid argument: {arg}")));
+    }
+
+    let mut summaries: BTreeMap<String, UsageSummary> = BTreeMap::new();
+    let mut total = UsageSummary::default();
+    for entry in read_entries()? {
+        if since.is_some_and(|since| entry.timestamp < since) {
+            continue;
+        }
+        summaries
+            .entry(group_by.key(&entry))
+            .or_default()
+            .add(&entry);
+        total.add(&entry);
+    }
+
+    if csv {
+        print_csv(group_by, &summaries);
+    } else {
+        print_table(group_by, &summaries, &total);
+    }
+    Ok(())
+}
+
+/// Accepts a date like `2024-05-01` or a number of days like `7d`
+fn parse_since(since: &str) -> Result<DateTime<Utc>> {
+    if let Some(days) = since.strip_suffix('d').and_then(|days| days.parse().ok()) {
+        // a negative count would look into the future
+        return TimeDelta::try_days(days)
+            .filter(|_| days >= 0)
+            .and_then(|duration| Utc::now().checked_sub_signed(duration))
+            .ok_or_else(|| {
+                LlmError::Usage(format!(
+                    "Could not go back {days} days for since '{since}', use a count like 7d"
+                ))
+            });
+    }
+
+    NaiveDate::parse_from_str(since, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .map(|midnight| midnight.with_timezone(&Utc))
+        .ok_or_else(|| {
+            LlmError::Usage(format!(
+                "Could not parse since '{since}', use a date like 2024-05-01 or days like 7d"
+            ))
+        })
+}
+
+fn print_table(
+    group_by: GroupBy,
+    summaries: &BTreeMap<String, UsageSummary>,
+    total: &UsageSummary,
+) {
+    if summaries.is_empty() {
+        println!("No requests recorded");
+        return;
+    }
+
+    let key_width = summaries
+        .keys()
+        .map(|key| key.chars().count())
+        .max()
+        .unwrap_or_default()
+        .max(group_by.column_name().len())
+        .max("total".len());
+    println!(
+        "{:<key_width$}  {:>8}  {:>6}  {:>13}  {:>17}  {:>10}",
+        group_by.column_name(),
+        "requests",
+        "failed",
+        "prompt tokens",
+        "completion tokens",
+        "cost"
+    );
+    let print_row = |key: &str, summary: &UsageSummary| {
+        println!(
+            "{:<key_width$}  {:>8}  {:>6}  {:>13}  {:>17}  {:>10}",
+            key,
+            summary.requests,
+            summary.failed,
+            summary.prompt_tokens,
+            summary.completion_tokens,
+            format!("${:.4}", summary.cost)
+        );
+    };
+    for (key, summary) in summaries {
+        print_row(key, summary);
+    }
+    print_row("total", total);
+}
+
+fn print_csv(group_by: GroupBy, summaries: &BTreeMap<String, UsageSummary>) {
+    println!(
+        "{},requests,failed,prompt_tokens,completion_tokens,cost_usd",
+        group_by.column_name()
+    );
+    for (key, summary) in summaries {
+        println!(
+            "{},{},{},{},{},{:.6}",
+            csv_field(key),
+            summary.requests,
+            summary.failed,
+            summary.prompt_tokens,
+            summary.completion_tokens,
+            summary.cost
+        );
+    }
+}
+
+/// Model names from config files can hold anything, quote them where CSV needs it
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
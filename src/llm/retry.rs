@@ -0,0 +1,153 @@
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use std::time::Duration;
+
+/// Longest we ever wait between two attempts, even if the provider asks for more
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(120);
+
+/// Rate limits (429) and server errors, including Anthropic's 529 "overloaded", are worth another try
+pub fn is_retryable_status(status: u16) -> bool {
+    status == 429 || ((500..600).contains(&status) && status != 501)
+}
+
+/// Time to wait before retry number `attempt` (starting at 1).
+///
+/// Hints of the provider (`Retry-After` and the rate limit reset headers) are honored,
+/// otherwise the delay doubles with every attempt and is jittered so parallel
+/// requests do not hit the provider at the same moment again.
+pub fn get_retry_delay(
+    headers: Option<&HeaderMap>,
+    attempt: u32,
+    base_delay: Duration,
+) -> Duration {
+    if let Some(delay) = headers.and_then(get_provider_delay) {
+        return delay.min(MAX_RETRY_DELAY);
+    }
+
+    let delay = base_delay
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY);
+    let jitter = rand::thread_rng().gen_range(0.5..=1.0);
+    delay.mul_f64(jitter)
+}
+
+fn get_provider_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if let Some(milliseconds) = header("retry-after-ms").and_then(|value| value.parse().ok()) {
+        return Some(Duration::from_millis(milliseconds));
+    }
+    if let Some(seconds) = header("retry-after").and_then(|value| value.parse().ok()) {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    // OpenAI style limits, only the exhausted ones tell us how long to wait
+    ["requests", "tokens"]
+        .iter()
+        .filter(|kind| header(&format!("x-ratelimit-remaining-{kind}")) == Some("0"))
+        .filter_map(|kind| header(&format!("x-ratelimit-reset-{kind}")))
+        .filter_map(parse_reset_duration)
+        .max()
+}
+
+/// Parses durations like `20ms`, `1s` or `6m0.5s` as used in the OpenAI rate limit headers
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut duration = Duration::ZERO;
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let number: f64 = rest[..number_end]
+            .parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())?;
+        rest = &rest[number_end..];
+
+        let unit_end = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let seconds = match &rest[..unit_end] {
+            "ms" => number / 1000.0,
+            "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            _ => return None,
+        };
+        // absurd values are ignored like unknown ones instead of overflowing
+        duration = duration.checked_add(Duration::try_from_secs_f64(seconds).ok()?)?;
+        rest = &rest[unit_end..];
+    }
+    Some(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderName, HeaderValue};
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_reset_durations() {
+        assert_eq!(
+            parse_reset_duration("6m0.5s"),
+            Some(Duration::from_millis(360_500))
+        );
+        assert_eq!(
+            parse_reset_duration("20ms"),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(parse_reset_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_reset_duration("5"), None);
+        assert_eq!(parse_reset_duration("99999999999999999999999s"), None);
+        assert_eq!(parse_reset_duration("1e300s"), None);
+    }
+
+    #[test]
+    fn honors_provider_delays() {
+        let exhausted = headers(&[
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-reset-requests", "6m0.5s"),
+            ("x-ratelimit-remaining-tokens", "10"),
+            ("x-ratelimit-reset-tokens", "1h"),
+        ]);
+        assert_eq!(
+            get_provider_delay(&exhausted),
+            Some(Duration::from_millis(360_500))
+        );
+        assert_eq!(
+            get_provider_delay(&headers(&[("retry-after-ms", "20")])),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(
+            get_provider_delay(&headers(&[("retry-after", "3")])),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            get_provider_delay(&headers(&[
+                ("x-ratelimit-remaining-requests", "0"),
+                ("x-ratelimit-reset-requests", "5"),
+            ])),
+            None
+        );
+    }
+
+    #[test]
+    fn ignores_overflowing_resets() {
+        let overflowing = headers(&[
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-reset-requests", "99999999999999999999999s"),
+        ]);
+        assert_eq!(get_provider_delay(&overflowing), None);
+        assert!(get_retry_delay(Some(&overflowing), 1, Duration::from_secs(1)) <= MAX_RETRY_DELAY);
+    }
+}
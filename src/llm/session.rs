@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::llm::error::{LlmError, Result};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+impl Role {
+    /// Role name as used by the OpenAI style chat APIs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+/// Provider independent chat message, every provider maps it to its own format
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn user(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: Role::User,
+            content: content.to_owned(),
+        }
+    }
+
+    pub fn assistant(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: Role::Assistant,
+            content: content.to_owned(),
+        }
+    }
+}
+
+/// A named conversation, stored as JSON in the user's data directory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub name: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl Session {
+    /// Loads the session with the given name or starts a new one if it does not exist yet
+    pub fn load(name: &str) -> Result<Session> {
+        let session_path = get_session_path(name)?;
+        if !session_path.exists() {
+            return Ok(Session {
+                name: name.to_owned(),
+                messages: Vec::new(),
+            });
+        }
+
+        let json_string = fs::read_to_string(&session_path).map_err(|error| {
+            LlmError::Config(format!("Could not read session '{name}': {error}"))
+        })?;
+        serde_json::from_str(&json_string)
+            .map_err(|error| LlmError::Config(format!("Could not parse session '{name}': {error}")))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let session_path = get_session_path(&self.name)?;
+        if let Some(session_dir) = session_path.parent() {
+            fs::create_dir_all(session_dir).map_err(|error| {
+                LlmError::Config(format!("Could not create the session directory: {error}"))
+            })?;
+        }
+
+        let json_string = serde_json::to_string_pretty(self)
+            .map_err(|error| LlmError::Config(format!("Could not stringify session: {error}")))?;
+        fs::write(&session_path, json_string).map_err(|error| {
+            LlmError::Config(format!("Could not write session '{}': {error}", self.name))
+        })
+    }
+
+    /// The prior history followed by the new prompt, which is what gets sent to the provider
+    pub fn with_prompt(&self, prompt: &str) -> Vec<ChatMessage> {
+        let mut messages = self.messages.clone();
+        messages.push(ChatMessage::user(prompt));
+        messages
+    }
+
+    pub fn push_turn(&mut self, prompt: &str, response: &str) {
+        self.messages.push(ChatMessage::user(prompt));
+        self.messages.push(ChatMessage::assistant(response));
+    }
+}
+
+fn get_session_path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(LlmError::Usage(format!("Invalid session name: {name}")));
+    }
+
+    let mut session_path = dirs::data_dir()
+        .ok_or_else(|| LlmError::Config(String::from("Could not find the user data directory")))?;
+    session_path.push("llm-cli");
+    session_path.push("sessions");
+    session_path.push(format!("{name}.json"));
+    Ok(session_path)
+}
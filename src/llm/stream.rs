@@ -0,0 +1,58 @@
+/// How a provider frames the events of a streamed response
+#[derive(Clone, Copy, PartialEq)]
+pub enum StreamFormat {
+    /// `text/event-stream` with one JSON payload per `data:` line
+    Sse,
+    /// One JSON object per line
+    Ndjson,
+}
+
+/// Incremental parser for streamed response bodies.
+///
+/// Network chunks can end anywhere (even inside a UTF-8 character), so bytes are
+/// buffered until a full line is available.
+pub struct StreamParser {
+    format: StreamFormat,
+    buffer: Vec<u8>,
+}
+
+pub enum StreamEvent {
+    Data(String),
+    Done,
+}
+
+impl StreamParser {
+    pub fn new(format: StreamFormat) -> StreamParser {
+        StreamParser {
+            format,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Feeds a network chunk and returns the JSON payloads of all completed lines
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<StreamEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(newline) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            let data = match self.format {
+                // "event:", "id:" and comment lines carry nothing we need, the type is also in the data
+                StreamFormat::Sse => match line.strip_prefix("data:") {
+                    Some(data) => data.trim_start(),
+                    None => continue,
+                },
+                StreamFormat::Ndjson => line.trim(),
+            };
+            if data == "[DONE]" {
+                events.push(StreamEvent::Done);
+            } else if !data.is_empty() {
+                events.push(StreamEvent::Data(data.to_owned()));
+            }
+        }
+        events
+    }
+}
@@ -1,70 +1,284 @@
 use std::{
-    io::{stdout, Write},
-    path::PathBuf,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
-    thread,
-    time::Duration,
+    io::{stderr, stdout, IsTerminal, Write},
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, MutexGuard};
 
-pub fn parse_prompt(args: Vec<String>) -> String {
-    if args[0] != "$" {
-        panic!("--- Missing '$' command ---")
+use crate::llm::cache::ResponseCache;
+use crate::llm::output::OutputFormat;
+use crate::llm::provider::{Completion, Usage};
+
+/// Where a single model is at, shown next to its name while the requests are running
+#[derive(Clone, PartialEq)]
+pub enum ModelStatus {
+    Waiting,
+    Retrying { attempt: u32, max_retries: u32 },
+    Streaming,
+    Done,
+    Failed,
+}
+
+impl ModelStatus {
+    fn is_finished(&self) -> bool {
+        matches!(self, ModelStatus::Done | ModelStatus::Failed)
     }
+}
 
-    args[1..].join(" ")
+struct ModelProgress {
+    label: String,
+    status: ModelStatus,
+    start: Instant,
+    /// Set once the model is done or failed, so its timer stops
+    end: Option<Instant>,
 }
 
-pub fn get_config_path() -> PathBuf {
-    let mut config_path = PathBuf::new();
-    match std::env::consts::OS {
-        "linux" | "macos" => config_path.push("/etc/llm_cli_config.json"),
-        _ => {
-            panic!("--- Unsupported operating system ---")
+/// State shared between the requests of one run and the progress indicator
+#[derive(Clone)]
+pub struct RequestState {
+    /// Every model of the run in the order they were asked
+    progress: Arc<std::sync::Mutex<Vec<ModelProgress>>>,
+    /// Held by whoever is printing, so outputs do not get mixed. It counts the lines of the
+    /// progress indicator currently on the screen, which have to be cleared before printing.
+    lock: Arc<Mutex<usize>>,
+    pub format: OutputFormat,
+    /// `None` if the cache is disabled or turned off with `-no-cache`
+    pub cache: Option<ResponseCache>,
+}
+
+impl RequestState {
+    pub fn new(
+        model_labels: &[String],
+        format: OutputFormat,
+        cache: Option<ResponseCache>,
+    ) -> RequestState {
+        let progress = model_labels
+            .iter()
+            .map(|label| ModelProgress {
+                label: label.clone(),
+                status: ModelStatus::Waiting,
+                start: Instant::now(),
+                end: None,
+            })
+            .collect();
+        RequestState {
+            progress: Arc::new(std::sync::Mutex::new(progress)),
+            lock: Arc::new(Mutex::new(0)),
+            format,
+            cache,
         }
     }
-    config_path
+
+    /// Waits until nobody else is printing and clears the progress indicator, it is drawn
+    /// again once the returned guard is dropped
+    pub async fn lock_output(&self) -> MutexGuard<'_, usize> {
+        let mut drawn_lines = self.lock.lock().await;
+        clear_lines(&mut drawn_lines);
+        drawn_lines
+    }
+
+    /// Updates the model at `model_index` in the list given to `new`
+    pub fn set_status(&self, model_index: usize, status: ModelStatus) {
+        let mut progress = self.progress.lock().unwrap();
+        // a model that is finished stays that way
+        let Some(model) = progress
+            .get_mut(model_index)
+            .filter(|model| !model.status.is_finished())
+        else {
+            return;
+        };
+        if status.is_finished() {
+            model.end = Some(Instant::now());
+        }
+        model.status = status;
+    }
+
+    /// Handle for the request of the model at `model_index`, see `PendingRequest`
+    pub fn pending_request(&self, model_index: usize) -> PendingRequest {
+        PendingRequest {
+            state: self.clone(),
+            model_index,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        let progress = self.progress.lock().unwrap();
+        progress.iter().all(|model| model.status.is_finished())
+    }
+
+    fn progress_lines(&self, frame: usize) -> Vec<String> {
+        let progress = self.progress.lock().unwrap();
+        let spinner = SPINNER_FRAMES[frame % SPINNER_FRAMES.len()];
+        progress
+            .iter()
+            .map(|model| {
+                let (symbol, status) = match &model.status {
+                    ModelStatus::Waiting => (spinner, String::from("waiting")),
+                    ModelStatus::Retrying {
+                        attempt,
+                        max_retries,
+                    } => (spinner, format!("retrying ({attempt}/{max_retries})")),
+                    ModelStatus::Streaming => (spinner, String::from("streaming")),
+                    ModelStatus::Done => ("+", String::from("done")),
+                    ModelStatus::Failed => ("x", String::from("failed")),
+                };
+                let elapsed = model.end.unwrap_or_else(Instant::now) - model.start;
+                format!(
+                    "{symbol} {} {status} {:.1}s",
+                    model.label,
+                    elapsed.as_secs_f64()
+                )
+            })
+            .collect()
+    }
+}
+
+/// Marks the request of one model as failed when dropped before `finish` was called,
+/// so neither an error nor a panic can keep the progress indicator running
+pub struct PendingRequest {
+    state: RequestState,
+    model_index: usize,
+}
+
+impl PendingRequest {
+    pub fn set_status(&self, status: ModelStatus) {
+        self.state.set_status(self.model_index, status);
+    }
+
+    pub fn finish(self) {
+        self.set_status(ModelStatus::Done);
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        // no effect after `finish`, as finished models keep their status
+        self.set_status(ModelStatus::Failed);
+    }
+}
+
+const SPINNER_FRAMES: [&str; 4] = ["|", "/", "-", "\\"];
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Moves the cursor up to the first drawn line and clears everything below
+fn clear_lines(drawn_lines: &mut usize) {
+    if *drawn_lines > 0 {
+        eprint!("\x1b[{}F\x1b[J", *drawn_lines);
+        let _ = stderr().flush();
+        *drawn_lines = 0;
+    }
 }
 
-pub fn print_response(response: &String, req_time: f64, divider_number: usize, llm_name: &str) {
+pub fn print_response(completion: &Completion, llm_name: &str) {
+    print_response_header(llm_name, completion.cached);
+    println!("{}\n", completion.text);
+    print_response_footer(completion);
+}
+
+pub fn print_response_header(llm_name: &str, cached: bool) {
     println!(
-        "{} {} Response (took {:.2} seconds) {}\n",
+        "{} {} Response{} {}\n",
         "-".repeat(10),
         llm_name,
-        req_time,
+        if cached { " (cached)" } else { "" },
+        "-".repeat(10)
+    );
+}
+
+pub fn print_response_footer(completion: &Completion) {
+    println!(
+        "{} took {:.2} seconds{} {}\n",
+        "-".repeat(10),
+        completion.latency,
+        format_usage(&completion.usage, completion.cost),
+        "-".repeat(10)
+    );
+}
+
+/// Footer of several answers, so they can be compared by price as well
+pub fn print_total_footer(usages: &[(Usage, Option<f64>)]) {
+    let mut total_usage = Usage::default();
+    let mut total_cost = None;
+    for (usage, cost) in usages {
+        total_usage.prompt_tokens = sum_tokens(total_usage.prompt_tokens, usage.prompt_tokens);
+        total_usage.completion_tokens =
+            sum_tokens(total_usage.completion_tokens, usage.completion_tokens);
+        if let Some(cost) = cost {
+            total_cost = Some(total_cost.unwrap_or(0.0) + cost);
+        }
+    }
+    let mut usage_text = format_usage(&total_usage, total_cost);
+    // a total that leaves some models out must not pass for the full cost
+    if total_cost.is_some() && usages.iter().any(|(_, cost)| cost.is_none()) {
+        usage_text.push_str(" + unknown");
+    }
+    println!(
+        "{} total of {} answers{usage_text} {}\n",
+        "-".repeat(10),
+        usages.len(),
         "-".repeat(10)
     );
-    println!("{}\n", response);
-    println!("{}\n", "-".repeat(divider_number));
-}
-
-pub async fn loop_loading(
-    loading_text: &str,
-    prompt: Arc<String>,
-    request_number: Arc<AtomicUsize>,
-    lock: Arc<Mutex<()>>,
-) {
-    // acquire lock to block request threads to not send response message immediately
-    let _lock = lock.lock().await;
-    let dot_number = 3;
-    println!();
-
-    // wait for atomic signals sent upon response finish from request threads
-    while request_number.load(Ordering::SeqCst) != 0 {
-        print!("{}", loading_text);
-        stdout().flush().unwrap();
-        thread::sleep(Duration::from_secs(1));
-        for _ in 0..dot_number {
-            if request_number.load(Ordering::SeqCst) != 0 {
-                print!(".");
-                stdout().flush().unwrap();
-                thread::sleep(Duration::from_secs(1));
+}
+
+fn sum_tokens(total: Option<u32>, tokens: Option<u32>) -> Option<u32> {
+    match (total, tokens) {
+        (Some(total), Some(tokens)) => Some(total + tokens),
+        (total, tokens) => total.or(tokens),
+    }
+}
+
+/// Token counts and cost as far as they are known, like `, 17 in / 612 out tokens, $0.0122`
+fn format_usage(usage: &Usage, cost: Option<f64>) -> String {
+    let mut text = String::new();
+    if let (Some(prompt_tokens), Some(completion_tokens)) =
+        (usage.prompt_tokens, usage.completion_tokens)
+    {
+        text.push_str(&format!(
+            ", {prompt_tokens} in / {completion_tokens} out tokens"
+        ));
+    } else if let Some(completion_tokens) = usage.completion_tokens {
+        text.push_str(&format!(", {completion_tokens} out tokens"));
+    }
+    // a short answer of a small model costs fractions of a cent
+    match cost {
+        Some(cost) if cost < 0.01 => text.push_str(&format!(", ${cost:.6}")),
+        Some(cost) => text.push_str(&format!(", ${cost:.4}")),
+        None => {}
+    }
+    text
+}
+
+/// Echoes the prompt above the answers, before any request is started so that not even
+/// a cached answer comes first
+pub fn print_prompt(prompt: &str, format: OutputFormat) {
+    // scripts reading our output want neither the progress nor the echoed prompt in their data
+    if format == OutputFormat::Text {
+        print!("{}\n\n", prompt);
+    }
+}
+
+/// Shows the status and elapsed time of every model on stderr until all of them are finished
+pub async fn show_progress(state: RequestState) {
+    if !stdout().is_terminal() || !stderr().is_terminal() {
+        return;
+    }
+
+    let mut frame = 0;
+    loop {
+        {
+            // whoever prints an answer holds the lock, the progress waits until it is done
+            let mut drawn_lines = state.lock_output().await;
+            if state.is_finished() {
+                break;
+            }
+            let progress_lines = state.progress_lines(frame);
+            for line in &progress_lines {
+                eprintln!("{line}");
             }
+            *drawn_lines = progress_lines.len();
         }
-        print!("\r{}{}", " ".repeat(50), "\x08".repeat(50));
+        frame += 1;
+        tokio::time::sleep(PROGRESS_INTERVAL).await;
     }
-    print!("{}\n\n", prompt);
 }
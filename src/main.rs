@@ -1,177 +1,422 @@
-use llm_cli::llm::claude::CLAUDE;
-use llm_cli::llm::gpt::GPT;
-use llm_cli::llm::mistral::MISTRAL;
-use llm_cli::llm::utils::{loop_loading, parse_prompt};
-use std::collections::HashMap;
+use llm_cli::llm::budget::{check_budget, estimate_cost};
+use llm_cli::llm::cache::ResponseCache;
+use llm_cli::llm::cli::{
+    parse_command, parse_model_list, parse_prompt, print_help, print_version, run_config_command,
+    run_models_command, Command,
+};
+use llm_cli::llm::error::{LlmError, Result};
+use llm_cli::llm::input::read_prompt;
+use llm_cli::llm::output::{
+    print_json, print_ndjson, ErrorRecord, ModelRecord, OutputFormat, ResponseRecord,
+};
+use llm_cli::llm::provider::{ask, get_provider, LlmProvider, Usage};
+use llm_cli::llm::repl::run_repl;
+use llm_cli::llm::report::run_usage_report;
+use llm_cli::llm::session::{ChatMessage, Session};
+use llm_cli::llm::utils::{print_prompt, print_total_footer, show_progress, RequestState};
 use std::env;
-use std::sync::atomic::AtomicUsize;
+use std::process;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
-#[derive(Clone)]
-pub enum LlmModel {
-    GPT(GPT),
-    CLAUDE(CLAUDE),
-    MISTRAL(MISTRAL),
-}
+/// Exit code after a panic, the panic message itself is already printed by then
+const PANIC_EXIT_CODE: i32 = 9;
 
 #[tokio::main]
 async fn main() {
-    let mut args: Vec<String> = env::args().collect();
-    if args[0] == "llm-cli" {
-        args.remove(0);
+    // Rust ignores SIGPIPE, so `llm-cli ... | head -1` would panic in println! once head is done,
+    // with the default handling the app simply stops like other command line tools
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
     }
 
-    let models = get_models(&args[0]);
-    let other_args = args[1..].to_vec();
-    let all_requests = tokio::spawn(async move {
-        handle_model_requests(models, &args[0], other_args).await;
-    });
+    let args: Vec<String> = env::args().collect();
+
+    let all_requests = tokio::spawn(async move { run(args).await });
 
-    all_requests.await.unwrap();
+    match all_requests.await {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            eprintln!("--- {error} ---");
+            process::exit(error.exit_code());
+        }
+        Err(_) => {
+            eprintln!("--- llm-cli stopped unexpectedly, please report the message above ---");
+            process::exit(PANIC_EXIT_CODE);
+        }
+    }
 }
 
-fn get_models(model_args: &str) -> Vec<LlmModel> {
-    let mut models: Vec<LlmModel> = Vec::new();
-    let model_args = model_args.split(',');
-    for model in model_args {
-        match model {
-            "gpt" => models.push(LlmModel::GPT(GPT::default())),
-            "claude" => models.push(LlmModel::CLAUDE(CLAUDE::default())),
-            "mistral" => models.push(LlmModel::MISTRAL(MISTRAL::default())),
-            _ => panic!(
-                "--- Invalid first argument, choose between 'gpt', 'gemini' and 'claude' or a combination of those seperated by ',' ---"
-            ),
+async fn run(args: Vec<String>) -> Result<()> {
+    match parse_command(args)? {
+        Command::Run { models, args } => {
+            let mut models = get_models(&models)?;
+            match models.len() {
+                1 => handle_single_request(models.remove(0), args, SingleMode::AskOrChat).await,
+                _ => handle_multiple_requests(args, models).await,
+            }
+        }
+        Command::Ask { model, args } => {
+            let model_entry = get_single_model(&model, "ask")?;
+            handle_single_request(model_entry, args, SingleMode::Ask).await
+        }
+        Command::Chat { model, args } => {
+            let model_entry = get_single_model(&model, "chat")?;
+            handle_single_request(model_entry, args, SingleMode::Chat).await
+        }
+        Command::Compare { models, args } => {
+            let models = get_models(&models)?;
+            if models.len() < 2 {
+                return Err(LlmError::Usage(String::from(
+                    "'compare' needs at least two models, e.g. llm-cli compare gpt,claude $ <prompt>",
+                )));
+            }
+            handle_multiple_requests(args, models).await
+        }
+        Command::Config => run_config_command(),
+        Command::Models => run_models_command(),
+        Command::Usage(args) => run_usage_report(args),
+        Command::Help(help) => {
+            print_help(help);
+            Ok(())
+        }
+        Command::Version => {
+            print_version();
+            Ok(())
         }
     }
-    models
 }
 
-async fn handle_model_requests(models: Vec<LlmModel>, model_arg: &str, args: Vec<String>) {
-    let models_len = models.len();
+/// Whether a single model gets one prompt, a conversation or whatever the arguments ask for
+#[derive(Clone, Copy, PartialEq)]
+enum SingleMode {
+    Ask,
+    Chat,
+    /// A conversation without a prompt or with `-i`, one answer otherwise
+    AskOrChat,
+}
+
+/// A model of the model list together with what was given behind its name
+struct ModelEntry {
+    model: Box<dyn LlmProvider>,
+    /// The model after `:` followed by the parameters in brackets
+    params: Vec<(String, String)>,
+    /// Whether the model name is part of the label, so `gpt:gpt-4o,gpt:gpt-4o-mini` can be told apart
+    show_model: bool,
+}
 
-    // if only one model gets selected
-    if models_len == 1 {
-        handle_single_request(models[0].clone(), args).await;
-        return;
+impl ModelEntry {
+    /// Sets the parameters of the model, the ones behind its name win over the shared flags
+    fn apply_params(&mut self, shared_params: &[(String, String)]) -> Result<()> {
+        for (name, value) in shared_params.iter().chain(&self.params) {
+            self.model.set_param(name, value)?;
+        }
+        if self.show_model {
+            let label = format!("{} ({})", self.model.name(), self.model.config().model_name);
+            self.model.config_mut().label = Some(label);
+        }
+        Ok(())
     }
+}
+
+fn get_models(model_args: &str) -> Result<Vec<ModelEntry>> {
+    let model_specs = parse_model_list(model_args)?;
+    let provider_names: Vec<&str> = model_specs
+        .iter()
+        .map(|model_spec| model_spec.provider.as_str())
+        .collect();
 
-    // if a combination of models get selected
-    handle_multiple_requests(model_arg, args, models).await;
+    let mut models = Vec::new();
+    for model_spec in &model_specs {
+        let mut params = Vec::new();
+        if let Some(model_name) = &model_spec.model {
+            params.push((String::from("model"), model_name.clone()));
+        }
+        params.extend(model_spec.params.iter().cloned());
+
+        // the same provider twice is only useful with different models
+        let provider_count = provider_names
+            .iter()
+            .filter(|provider_name| **provider_name == model_spec.provider)
+            .count();
+        models.push(ModelEntry {
+            model: get_provider(&model_spec.provider)?,
+            params,
+            show_model: model_spec.model.is_some() || provider_count > 1,
+        });
+    }
+    Ok(models)
 }
 
-async fn handle_single_request(model: LlmModel, args: Vec<String>) {
-    let request_number_mutex = Arc::new(AtomicUsize::new(1));
-    let lock_mutex = Arc::new(Mutex::new(()));
-    let lock = Arc::clone(&lock_mutex);
-    match model {
-        LlmModel::GPT(mut x) => {
-            let prompt_mutex = Arc::new(x.parse_args(args));
-            let prompt = Arc::clone(&prompt_mutex);
-            let request_number = Arc::clone(&request_number_mutex);
-            tokio::spawn(async move {
-                loop_loading(
-                    "Asking GPT",
-                    prompt,
-                    request_number,
-                    Arc::clone(&lock_mutex),
-                )
-                .await
-            });
-            let prompt = Arc::clone(&prompt_mutex);
-            let request_number = Arc::clone(&request_number_mutex);
-            x.make_request(prompt, request_number, lock).await;
-        }
-        LlmModel::CLAUDE(mut x) => {
-            let prompt_mutex = Arc::new(x.parse_args(args));
-            let prompt = Arc::clone(&prompt_mutex);
-            let request_number = Arc::clone(&request_number_mutex);
-            tokio::spawn(async move {
-                loop_loading(
-                    "Asking Claude",
-                    prompt,
-                    request_number,
-                    Arc::clone(&lock_mutex),
-                )
-                .await
-            });
-            let prompt = Arc::clone(&prompt_mutex);
-            let request_number = Arc::clone(&request_number_mutex);
-            x.make_request(prompt, request_number, lock).await;
-        }
-        LlmModel::MISTRAL(mut x) => {
-            let prompt_mutex = Arc::new(x.parse_args(args));
-            let prompt = Arc::clone(&prompt_mutex);
-            let request_number = Arc::clone(&request_number_mutex);
-            tokio::spawn(async move {
-                loop_loading(
-                    "Asking Mistral",
-                    prompt,
-                    request_number,
-                    Arc::clone(&lock_mutex),
-                )
-                .await
-            });
-            let prompt = Arc::clone(&prompt_mutex);
-            let request_number = Arc::clone(&request_number_mutex);
-            x.make_request(prompt, request_number, lock).await;
+/// Adds the parameters in brackets to labels that are the same otherwise, like for
+/// `gpt[temp=0.2],gpt[temp=0.9]`, and a number if even those are the same
+fn make_labels_unique(model_entries: &mut [ModelEntry]) {
+    let labels: Vec<String> = model_entries
+        .iter()
+        .map(|model_entry| model_entry.model.label().to_owned())
+        .collect();
+    let labels: Vec<String> = model_entries
+        .iter()
+        .zip(&labels)
+        .map(|(model_entry, label)| {
+            let params: Vec<String> = model_entry
+                .params
+                .iter()
+                .filter(|(name, _)| name != "model")
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            match count_label(&labels, label) > 1 && !params.is_empty() {
+                true => format!("{label} [{}]", params.join(",")),
+                false => label.clone(),
+            }
+        })
+        .collect();
+
+    for (index, model_entry) in model_entries.iter_mut().enumerate() {
+        let label = &labels[index];
+        let label = match count_label(&labels, label) > 1 {
+            true => format!("{label} #{}", count_label(&labels[..=index], label)),
+            false => label.clone(),
+        };
+        model_entry.model.config_mut().label = Some(label);
+    }
+}
+
+fn count_label(labels: &[String], label: &str) -> usize {
+    labels.iter().filter(|other| *other == label).count()
+}
+
+fn get_single_model(model_args: &str, command: &str) -> Result<ModelEntry> {
+    let mut models = get_models(model_args)?;
+    if models.len() > 1 {
+        return Err(LlmError::Usage(format!(
+            "'{command}' works with a single model, use 'llm-cli compare {model_args}' for several"
+        )));
+    }
+    Ok(models.remove(0))
+}
+
+async fn handle_single_request(
+    mut model_entry: ModelEntry,
+    args: Vec<String>,
+    mode: SingleMode,
+) -> Result<()> {
+    let mut prompt_args = parse_prompt(args)?;
+    model_entry.apply_params(&prompt_args.params)?;
+    match mode {
+        SingleMode::Ask if prompt_args.interactive => {
+            return Err(LlmError::Usage(String::from(
+                "-i does not work with 'ask', use 'llm-cli chat' instead",
+            )));
+        }
+        SingleMode::Chat => prompt_args.interactive = true,
+        _ => {}
+    }
+    let model = model_entry.model;
+    let mut session = match &prompt_args.session {
+        Some(session_name) => Some(Session::load(session_name)?),
+        None => None,
+    };
+
+    // the interactive mode reads stdin itself
+    let prompt = read_prompt(&prompt_args, !prompt_args.interactive)?;
+
+    // without a prompt there is nothing to send right away, so we start a conversation
+    let prompt = match prompt {
+        Some(prompt) if !prompt_args.interactive => prompt,
+        None if mode == SingleMode::Ask => {
+            return Err(LlmError::Usage(String::from(
+                "Missing prompt, e.g. llm-cli ask gpt $ <prompt>",
+            )));
+        }
+        first_prompt => {
+            if prompt_args.format != OutputFormat::Text {
+                return Err(LlmError::Usage(String::from(
+                    "-format only works for a single prompt, not in interactive mode",
+                )));
+            }
+            return run_repl(
+                model,
+                session,
+                first_prompt.map(|prompt| prompt.text),
+                prompt_args.cache_mode,
+            )
+            .await;
         }
+    };
+    let messages = match &session {
+        Some(session) => session.with_prompt(&prompt.text),
+        None => vec![ChatMessage::user(&prompt.text)],
+    };
+    let completion = ask(
+        model.as_ref(),
+        messages,
+        prompt.display,
+        prompt_args.format,
+        prompt_args.cache_mode,
+    )
+    .await?;
+    if prompt_args.format == OutputFormat::Json {
+        print_json(&ResponseRecord::new(
+            model.name(),
+            &model.config().model_name,
+            &prompt.text,
+            &completion,
+        ));
     }
+
+    if let Some(session) = &mut session {
+        session.push_turn(&prompt.text, &completion.text);
+        session.save()?;
+    }
+    Ok(())
 }
 
-async fn handle_multiple_requests(model_arg: &str, args: Vec<String>, models: Vec<LlmModel>) {
-    let mut label_map: HashMap<&str, &str> = HashMap::new();
-    label_map.insert("gpt", "GPT");
-    label_map.insert("claude", "Claude");
-    label_map.insert("mistral", "Mistral");
-
-    let prompt_mutex = Arc::new(parse_prompt(args));
-    let prompt = Arc::clone(&prompt_mutex);
-    let model_labels: Vec<&str> = model_arg
-        .split(',')
-        .map(|arg| *label_map.get(arg).unwrap())
+async fn handle_multiple_requests(args: Vec<String>, models: Vec<ModelEntry>) -> Result<()> {
+    let prompt_args = parse_prompt(args)?;
+    if prompt_args.session.is_some() {
+        return Err(LlmError::Usage(String::from(
+            "-session only works with a single model",
+        )));
+    }
+    if prompt_args.interactive {
+        return Err(LlmError::Usage(String::from(
+            "-i only works with a single model, use 'llm-cli chat' instead",
+        )));
+    }
+    let mut models = models;
+    for model_entry in &mut models {
+        model_entry.apply_params(&prompt_args.params)?;
+    }
+    make_labels_unique(&mut models);
+    let models: Vec<Box<dyn LlmProvider>> = models
+        .into_iter()
+        .map(|model_entry| model_entry.model)
+        .collect();
+    let format = prompt_args.format;
+    let prompt = read_prompt(&prompt_args, true)?
+        .ok_or_else(|| LlmError::Usage(String::from("Missing '$' command")))?;
+    let messages_mutex = Arc::new(vec![ChatMessage::user(&prompt.text)]);
+    let model_labels: Vec<String> = models
+        .iter()
+        .map(|model| model.label().to_owned())
+        .collect();
+    let cache = ResponseCache::open(prompt_args.cache_mode)?;
+    let estimated_cost = models
+        .iter()
+        .filter(|model| !model.is_cached(&messages_mutex, cache.as_ref()))
+        .map(|model| estimate_cost(model.as_ref(), &messages_mutex))
+        .sum();
+    check_budget(estimated_cost)?;
+
+    let state = RequestState::new(&model_labels, format, cache);
+
+    print_prompt(&prompt.display, format);
+    let progress_state = state.clone();
+    let progress_thread = tokio::spawn(async move { show_progress(progress_state).await });
+
+    // the models are moved into their tasks, a task that panics still needs its record
+    let failure_records: Vec<ErrorRecord> = models
+        .iter()
+        .map(|model| ErrorRecord {
+            provider: model.name().to_owned(),
+            model: model.config().model_name.clone(),
+            prompt: messages_mutex[0].content.clone(),
+            error: String::new(),
+        })
         .collect();
-    let loading_message = format!(
-        "Asking {}",
-        model_labels
-            .join(" and ")
-            .replacen(" and ", ", ", models.len() - 2)
-    );
-
-    let request_number_mutex = Arc::new(AtomicUsize::new(models.len()));
-    let request_number = Arc::clone(&request_number_mutex);
-    let lock_mutex = Arc::new(Mutex::new(()));
-    let lock = Arc::clone(&lock_mutex);
-
-    let loop_thread = tokio::spawn(async move {
-        loop_loading(loading_message.as_str(), prompt, request_number, lock).await
-    });
-
-    let mut tokio_threads = vec![loop_thread];
-    for model in models {
-        let lock = Arc::clone(&lock_mutex);
-        let prompt = Arc::clone(&prompt_mutex);
-        let request_number = Arc::clone(&request_number_mutex);
-        match model {
-            LlmModel::GPT(x) => {
-                let gpt_thread =
-                    tokio::spawn(async move { x.make_request(prompt, request_number, lock).await });
-                tokio_threads.push(gpt_thread);
+
+    let mut tokio_threads = Vec::new();
+    for (model_index, model) in models.into_iter().enumerate() {
+        let messages = Arc::clone(&messages_mutex);
+        let state = state.clone();
+        let failure = failure_records[model_index].clone();
+        let model_thread = tokio::spawn(async move {
+            let response = model
+                .make_request(messages.clone(), state.clone(), model_index, false)
+                .await;
+
+            // errors are printed right away, so the other answers are still shown
+            if let Err(error) = &response {
+                let _lock = state.lock_output().await;
+                eprintln!("--- {error} ---\n");
+                if state.format == OutputFormat::Ndjson {
+                    print_ndjson(&ErrorRecord {
+                        error: error.to_string(),
+                        ..failure
+                    });
+                }
             }
-            LlmModel::CLAUDE(x) => {
-                let claude_thread =
-                    tokio::spawn(async move { x.make_request(prompt, request_number, lock).await });
-                tokio_threads.push(claude_thread);
+            response.map(|completion| {
+                ResponseRecord::new(
+                    model.name(),
+                    &model.config().model_name,
+                    &messages[0].content,
+                    &completion,
+                )
+            })
+        });
+        tokio_threads.push(model_thread);
+    }
+
+    // a panicking model only loses its own answer
+    let mut records = Vec::new();
+    let mut failed_labels = Vec::new();
+    for ((thread, model_label), failure) in tokio_threads
+        .into_iter()
+        .zip(&model_labels)
+        .zip(failure_records)
+    {
+        match thread.await {
+            Ok(Ok(record)) => records.push(ModelRecord::Answer(record)),
+            Ok(Err(error)) => {
+                failed_labels.push(model_label.as_str());
+                records.push(ModelRecord::Failure(ErrorRecord {
+                    error: error.to_string(),
+                    ..failure
+                }));
             }
-            LlmModel::MISTRAL(x) => {
-                let claude_thread =
-                    tokio::spawn(async move { x.make_request(prompt, request_number, lock).await });
-                tokio_threads.push(claude_thread);
+            Err(_) => {
+                let error = format!("{model_label} failed unexpectedly");
+                let _lock = state.lock_output().await;
+                eprintln!("--- {error} ---\n");
+                let failure = ErrorRecord { error, ..failure };
+                if format == OutputFormat::Ndjson {
+                    print_ndjson(&failure);
+                }
+                failed_labels.push(model_label.as_str());
+                records.push(ModelRecord::Failure(failure));
             }
         }
     }
+    let _ = progress_thread.await;
+    let answered = records.len() - failed_labels.len();
+
+    // JSON is one document, so it can only be printed once every model is done
+    match format {
+        OutputFormat::Json => print_json(&records),
+        OutputFormat::Text if answered > 0 => {
+            let usages: Vec<(Usage, Option<f64>)> = records
+                .iter()
+                .filter_map(|record| match record {
+                    ModelRecord::Answer(record) => Some((record.usage, record.cost)),
+                    ModelRecord::Failure(_) => None,
+                })
+                .collect();
+            print_total_footer(&usages);
+        }
+        _ => {}
+    }
+    let mut summary = format!("{answered} of {} models answered", model_labels.len());
+    if !failed_labels.is_empty() {
+        summary.push_str(&format!(", failed: {}", failed_labels.join(", ")));
+    }
 
-    for thread in tokio_threads {
-        thread.await.unwrap();
+    // the failed models were already reported, so only the summary is left to show
+    let all_failed = answered == 0;
+    if all_failed || (prompt_args.strict && !failed_labels.is_empty()) {
+        return Err(LlmError::Failed(summary));
     }
+    eprintln!("--- {summary} ---");
+    Ok(())
 }
@@ -0,0 +1,113 @@
+use llm_cli::llm::cli::{parse_command, Command};
+use llm_cli::llm::error::LlmError;
+
+fn parse(args: &str) -> Result<Command, LlmError> {
+    parse_command(args.split(' ').map(String::from).collect())
+}
+
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn keeps_the_model_first_syntax() {
+    for program in [
+        "llm-cli",
+        "/usr/local/bin/llm-cli",
+        "./target/debug/llm-cli",
+        "llm-cli.exe",
+        "C:/tools/llm-cli/llm-cli.exe",
+    ] {
+        let command = parse(&format!("{program} gpt,claude -temp=0.5 $ hi there")).unwrap();
+        assert_eq!(
+            command,
+            Command::Run {
+                models: String::from("gpt,claude"),
+                args: strings(&["-temp=0.5", "$", "hi", "there"]),
+            },
+            "{program}"
+        );
+    }
+}
+
+#[test]
+fn uses_the_name_of_a_link_as_model() {
+    let command = parse("/usr/local/bin/gpt -token=100 $ hi").unwrap();
+    assert_eq!(
+        command,
+        Command::Run {
+            models: String::from("gpt"),
+            args: strings(&["-token=100", "$", "hi"]),
+        }
+    );
+    assert_eq!(
+        parse("gpt.exe $ hi").unwrap(),
+        Command::Run {
+            models: String::from("gpt"),
+            args: strings(&["$", "hi"]),
+        }
+    );
+}
+
+#[test]
+fn parses_subcommands() {
+    assert_eq!(
+        parse("llm-cli ask claude $ hi").unwrap(),
+        Command::Ask {
+            model: String::from("claude"),
+            args: strings(&["$", "hi"]),
+        }
+    );
+    assert_eq!(
+        parse("llm-cli chat gpt -session=tokyo").unwrap(),
+        Command::Chat {
+            model: String::from("gpt"),
+            args: strings(&["-session=tokyo"]),
+        }
+    );
+    assert_eq!(
+        parse("llm-cli compare gpt,claude $ hi").unwrap(),
+        Command::Compare {
+            models: String::from("gpt,claude"),
+            args: strings(&["$", "hi"]),
+        }
+    );
+    assert_eq!(parse("llm-cli models").unwrap(), Command::Models);
+    assert_eq!(parse("llm-cli config").unwrap(), Command::Config);
+    assert_eq!(
+        parse("llm-cli usage -by=model").unwrap(),
+        Command::Usage(strings(&["-by=model"]))
+    );
+    assert_eq!(parse("llm-cli --version").unwrap(), Command::Version);
+}
+
+#[test]
+fn shows_help_without_arguments() {
+    assert!(matches!(parse_command(Vec::new()), Ok(Command::Help(_))));
+    assert!(matches!(parse("llm-cli"), Ok(Command::Help(_))));
+}
+
+#[test]
+fn shows_help_only_before_the_prompt() {
+    assert!(matches!(parse("llm-cli --help"), Ok(Command::Help(_))));
+    assert!(matches!(parse("llm-cli help ask"), Ok(Command::Help(_))));
+    assert!(matches!(parse("llm-cli ask gpt -h"), Ok(Command::Help(_))));
+    assert!(matches!(
+        parse("llm-cli ask gpt $ what does -h do"),
+        Ok(Command::Ask { .. })
+    ));
+}
+
+#[test]
+fn rejects_invalid_commands() {
+    for args in [
+        "llm-cli --bogus",
+        "llm-cli $ hi",
+        "llm-cli ask",
+        "llm-cli ask -temp=0.5 $ hi",
+        "llm-cli models gpt",
+        "llm-cli help nope",
+    ] {
+        assert!(matches!(parse(args), Err(LlmError::Usage(_))), "{args}");
+    }
+}
@@ -0,0 +1,81 @@
+use llm_cli::llm::error::Result;
+use llm_cli::llm::output::OutputFormat;
+use llm_cli::llm::provider::{get_provider, Completion, LlmProvider};
+use llm_cli::llm::session::ChatMessage;
+use llm_cli::llm::utils::RequestState;
+use std::env;
+use std::sync::{Arc, OnceLock};
+use tempfile::TempDir;
+
+/// Config of the tested providers, the recorded fixtures depend on it as well
+const TEST_CONFIG: &str = r#"{
+    "gpt": {
+        "api_key": "test-key",
+        "model_name": "gpt-4o-mini",
+        "max_tokens": 64,
+        "temperature": 0.0
+    },
+    "claude": {
+        "api_key": "test-key",
+        "model_name": "claude-3-5-haiku-20241022",
+        "max_tokens": 64,
+        "temperature": 0.0
+    },
+    "mistral": {
+        "api_key": "test-key",
+        "model_name": "mistral-small-latest",
+        "max_tokens": 64,
+        "temperature": 0.0
+    }
+}"#;
+
+pub const PROMPT: &str = "Say hello";
+
+/// Keys and proxies of the machine running the tests, they would win over the test config
+const MACHINE_ENV: &[&str] = &[
+    "OPENAI_API_KEY",
+    "ANTHROPIC_API_KEY",
+    "MISTRAL_API_KEY",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "ALL_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "all_proxy",
+];
+
+/// Makes the test config the only config, the same for every test of a binary
+fn init_config() {
+    static CONFIG_DIR: OnceLock<TempDir> = OnceLock::new();
+    CONFIG_DIR.get_or_init(|| {
+        let config_dir = tempfile::tempdir().unwrap();
+        let config_path = config_dir.path().join("config.json");
+        std::fs::write(&config_path, TEST_CONFIG).unwrap();
+        env::set_var("LLM_CLI_CONFIG", &config_path);
+        env::set_var("LLM_CLI_CONFIG_ONLY", "1");
+        for name in MACHINE_ENV {
+            env::remove_var(name);
+        }
+        config_dir
+    });
+}
+
+/// The built-in provider `name`, talking to `base_url` instead of the real API if given
+pub fn provider(name: &str, base_url: Option<String>, stream: bool) -> Box<dyn LlmProvider> {
+    init_config();
+    let mut provider = get_provider(name).unwrap();
+    let config = provider.config_mut();
+    config.base_url = base_url;
+    config.stream = stream;
+    config.max_retries = 0;
+    config.read_timeout_secs = 1;
+    provider
+}
+
+/// Sends `PROMPT` without printing anything and without touching the usage ledger
+pub async fn complete(provider: &dyn LlmProvider) -> Result<Completion> {
+    let state = RequestState::new(&[provider.label().to_owned()], OutputFormat::Json, None);
+    provider
+        .request_completion(Arc::new(vec![ChatMessage::user(PROMPT)]), state, 0, false)
+        .await
+}
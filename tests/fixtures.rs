@@ -0,0 +1,66 @@
+mod common;
+
+use common::{complete, provider};
+use serde_json::json;
+use std::env;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// The fixture mode is read from the environment, so tests changing it must not overlap
+static FIXTURE_ENV: Mutex<()> = Mutex::const_new(());
+
+#[tokio::test]
+async fn replays_recorded_fixtures() {
+    let _env = FIXTURE_ENV.lock().await;
+    let fixture_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    env::set_var("LLM_CLI_REPLAY", &fixture_dir);
+
+    // no base URL, the requests would go to the real APIs without the fixtures
+    for (name, completion_tokens) in [("gpt", 9), ("claude", 12), ("mistral", 9)] {
+        let completion = complete(provider(name, None, true).as_ref()).await;
+        let completion = completion.unwrap_or_else(|error| panic!("{name}: {error}"));
+        assert_eq!(
+            completion.text, "Hello! How can I help you today?",
+            "{name}"
+        );
+        assert_eq!(
+            completion.usage.completion_tokens,
+            Some(completion_tokens),
+            "{name}"
+        );
+    }
+
+    env::remove_var("LLM_CLI_REPLAY");
+}
+
+#[tokio::test]
+async fn records_responses_for_replay() {
+    let _env = FIXTURE_ENV.lock().await;
+    let fixture_dir = tempfile::tempdir().unwrap();
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{"message": {"content": "Recorded"}, "finish_reason": "stop"}]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    env::set_var("LLM_CLI_RECORD", fixture_dir.path());
+    let base_url = Some(format!("{}/v1", server.uri()));
+    let recorded = complete(provider("gpt", base_url, false).as_ref()).await;
+    env::remove_var("LLM_CLI_RECORD");
+    assert_eq!(recorded.unwrap().text, "Recorded");
+    drop(server);
+
+    // the mock server is gone, only the fixture can answer now
+    env::set_var("LLM_CLI_REPLAY", fixture_dir.path());
+    let replayed = complete(provider("gpt", None, false).as_ref()).await;
+    let missing = complete(provider("claude", None, false).as_ref()).await;
+    env::remove_var("LLM_CLI_REPLAY");
+    assert_eq!(replayed.unwrap().text, "Recorded");
+    assert!(missing.is_err());
+}
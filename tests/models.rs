@@ -0,0 +1,70 @@
+use llm_cli::llm::cli::{parse_model_list, ModelSpec};
+use llm_cli::llm::error::LlmError;
+
+fn params(params: &[(&str, &str)]) -> Vec<(String, String)> {
+    params
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn parse(model_list: &str) -> Vec<ModelSpec> {
+    parse_model_list(model_list).unwrap_or_else(|error| panic!("{model_list}: {error}"))
+}
+
+#[test]
+fn parses_plain_providers() {
+    let model_specs = parse("gpt,claude");
+    assert_eq!(model_specs.len(), 2);
+    assert_eq!(model_specs[0].provider, "gpt");
+    assert_eq!(model_specs[1].provider, "claude");
+    assert!(model_specs
+        .iter()
+        .all(|model_spec| model_spec.model.is_none() && model_spec.params.is_empty()));
+}
+
+#[test]
+fn parses_model_names_and_params() {
+    let model_specs = parse("gpt:gpt-4o[temp=0.2, token=500],claude[system=be brief],gpt:o1");
+    assert_eq!(model_specs.len(), 3);
+
+    assert_eq!(model_specs[0].provider, "gpt");
+    assert_eq!(model_specs[0].model.as_deref(), Some("gpt-4o"));
+    assert_eq!(
+        model_specs[0].params,
+        params(&[("temp", "0.2"), ("token", "500")])
+    );
+
+    assert_eq!(model_specs[1].provider, "claude");
+    assert_eq!(model_specs[1].model, None);
+    assert_eq!(model_specs[1].params, params(&[("system", "be brief")]));
+
+    assert_eq!(model_specs[2].model.as_deref(), Some("o1"));
+    assert!(model_specs[2].params.is_empty());
+}
+
+#[test]
+fn accepts_empty_brackets() {
+    let model_specs = parse("gpt[]");
+    assert_eq!(model_specs[0].provider, "gpt");
+    assert!(model_specs[0].params.is_empty());
+}
+
+#[test]
+fn rejects_malformed_model_lists() {
+    for model_list in [
+        "gpt[temp=0.2[token=5]]",
+        "gpt]",
+        "gpt[temp=0.2",
+        "gpt[temp=0.2]x",
+        "gpt[temp]",
+        "gpt[max=5]",
+        "gpt:",
+        "gpt:[temp=0.2]",
+    ] {
+        assert!(
+            matches!(parse_model_list(model_list), Err(LlmError::Usage(_))),
+            "{model_list}"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use common::{complete, provider};
+use llm_cli::llm::error::LlmError;
+use serde_json::{json, Value};
+use std::time::Duration;
+use wiremock::matchers::{body_partial_json, header_exists, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// What a provider sends for the answer "Hello there!" to the test prompt
+struct ProviderCase {
+    name: &'static str,
+    path: &'static str,
+    auth_header: &'static str,
+    response: Value,
+    stream: String,
+    finish_reason: &'static str,
+}
+
+fn sse(events: &[Value]) -> String {
+    events
+        .iter()
+        .map(|event| format!("data: {event}\n\n"))
+        .collect()
+}
+
+fn openai_stream() -> String {
+    let mut stream = sse(&[
+        json!({"choices": [{"index": 0, "delta": {"role": "assistant", "content": ""}, "finish_reason": null}]}),
+        json!({"choices": [{"index": 0, "delta": {"content": "Hello"}, "finish_reason": null}]}),
+        json!({"choices": [{"index": 0, "delta": {"content": " there!"}, "finish_reason": null}]}),
+        json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}]}),
+        json!({"choices": [], "usage": {"prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12}}),
+    ]);
+    stream.push_str("data: [DONE]\n\n");
+    stream
+}
+
+fn openai_response() -> Value {
+    json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "Hello there!"},
+            "finish_reason": "stop"
+        }],
+        "usage": {"prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12}
+    })
+}
+
+fn claude_stream() -> String {
+    [
+        ("message_start", json!({"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "content": [], "stop_reason": null, "usage": {"input_tokens": 9, "output_tokens": 1}}})),
+        ("content_block_start", json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})),
+        ("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello"}})),
+        ("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": " there!"}})),
+        ("content_block_stop", json!({"type": "content_block_stop", "index": 0})),
+        ("message_delta", json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 3}})),
+        ("message_stop", json!({"type": "message_stop"})),
+    ]
+    .iter()
+    .map(|(event, data)| format!("event: {event}\ndata: {data}\n\n"))
+    .collect()
+}
+
+fn cases() -> Vec<ProviderCase> {
+    vec![
+        ProviderCase {
+            name: "gpt",
+            path: "/v1/chat/completions",
+            auth_header: "authorization",
+            response: openai_response(),
+            stream: openai_stream(),
+            finish_reason: "stop",
+        },
+        ProviderCase {
+            name: "claude",
+            path: "/v1/messages",
+            auth_header: "x-api-key",
+            response: json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "content": [{"type": "text", "text": "Hello there!"}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 9, "output_tokens": 3}
+            }),
+            stream: claude_stream(),
+            finish_reason: "end_turn",
+        },
+        ProviderCase {
+            name: "mistral",
+            path: "/v1/chat/completions",
+            auth_header: "authorization",
+            response: openai_response(),
+            stream: openai_stream(),
+            finish_reason: "stop",
+        },
+    ]
+}
+
+/// A mock server answering requests of `case` with `response`, the base URL goes to the provider
+async fn mock_server(case: &ProviderCase, stream: bool, response: ResponseTemplate) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(case.path))
+        .and(header_exists(case.auth_header))
+        .and(body_partial_json(
+            json!({"max_tokens": 64, "stream": stream}),
+        ))
+        .respond_with(response)
+        .expect(1)
+        .mount(&server)
+        .await;
+    server
+}
+
+fn base_url(server: &MockServer) -> Option<String> {
+    Some(format!("{}/v1", server.uri()))
+}
+
+#[tokio::test]
+async fn parses_answers() {
+    for case in cases() {
+        let response = ResponseTemplate::new(200).set_body_json(&case.response);
+        let server = mock_server(&case, false, response).await;
+
+        let completion = complete(provider(case.name, base_url(&server), false).as_ref())
+            .await
+            .unwrap_or_else(|error| panic!("{}: {error}", case.name));
+        assert_eq!(completion.text, "Hello there!", "{}", case.name);
+        assert_eq!(
+            completion.finish_reason.as_deref(),
+            Some(case.finish_reason),
+            "{}",
+            case.name
+        );
+        assert_eq!(completion.usage.prompt_tokens, Some(9), "{}", case.name);
+        assert_eq!(completion.usage.completion_tokens, Some(3), "{}", case.name);
+    }
+}
+
+#[tokio::test]
+async fn parses_streamed_answers() {
+    for case in cases() {
+        let response = ResponseTemplate::new(200)
+            .insert_header("content-type", "text/event-stream")
+            .set_body_string(&case.stream);
+        let server = mock_server(&case, true, response).await;
+
+        let completion = complete(provider(case.name, base_url(&server), true).as_ref())
+            .await
+            .unwrap_or_else(|error| panic!("{}: {error}", case.name));
+        assert_eq!(completion.text, "Hello there!", "{}", case.name);
+        assert_eq!(
+            completion.finish_reason.as_deref(),
+            Some(case.finish_reason),
+            "{}",
+            case.name
+        );
+        assert_eq!(completion.usage.prompt_tokens, Some(9), "{}", case.name);
+        assert_eq!(completion.usage.completion_tokens, Some(3), "{}", case.name);
+    }
+}
+
+#[tokio::test]
+async fn rejects_malformed_json() {
+    for case in cases() {
+        let response = ResponseTemplate::new(200).set_body_string("{\"choices\": [");
+        let server = mock_server(&case, false, response).await;
+
+        let result = complete(provider(case.name, base_url(&server), false).as_ref()).await;
+        assert!(matches!(result, Err(LlmError::Parse(_))), "{}", case.name);
+    }
+}
+
+#[tokio::test]
+async fn rejects_answers_in_an_unknown_shape() {
+    for case in cases() {
+        let response = ResponseTemplate::new(200).set_body_json(json!({"unexpected": true}));
+        let server = mock_server(&case, false, response).await;
+
+        let result = complete(provider(case.name, base_url(&server), false).as_ref()).await;
+        assert!(matches!(result, Err(LlmError::Parse(_))), "{}", case.name);
+    }
+}
+
+#[tokio::test]
+async fn rejects_malformed_stream_events() {
+    for case in cases() {
+        let response = ResponseTemplate::new(200)
+            .insert_header("content-type", "text/event-stream")
+            .set_body_string("data: {\"choices\": [\n\n");
+        let server = mock_server(&case, true, response).await;
+
+        let result = complete(provider(case.name, base_url(&server), true).as_ref()).await;
+        assert!(matches!(result, Err(LlmError::Parse(_))), "{}", case.name);
+    }
+}
+
+#[tokio::test]
+async fn reports_error_statuses() {
+    for case in cases() {
+        let error_body = json!({"error": {"type": "invalid_request_error", "message": "nope"}});
+
+        let server = mock_server(
+            &case,
+            false,
+            ResponseTemplate::new(401).set_body_json(&error_body),
+        )
+        .await;
+        let result = complete(provider(case.name, base_url(&server), false).as_ref()).await;
+        assert!(matches!(result, Err(LlmError::Auth(_))), "{}", case.name);
+
+        let server = mock_server(
+            &case,
+            false,
+            ResponseTemplate::new(500).set_body_json(&error_body),
+        )
+        .await;
+        let result = complete(provider(case.name, base_url(&server), false).as_ref()).await;
+        match result {
+            Err(LlmError::Http {
+                status, message, ..
+            }) => {
+                assert_eq!(status, 500, "{}", case.name);
+                assert!(message.contains("nope"), "{}", case.name);
+            }
+            other => panic!("{}: expected an HTTP error, got {other:?}", case.name),
+        }
+    }
+}
+
+#[tokio::test]
+async fn times_out_on_slow_providers() {
+    for case in cases() {
+        // the test providers give up after one second
+        let response = ResponseTemplate::new(200)
+            .set_body_json(&case.response)
+            .set_delay(Duration::from_secs(3));
+        let server = mock_server(&case, false, response).await;
+
+        let result = complete(provider(case.name, base_url(&server), false).as_ref()).await;
+        assert!(matches!(result, Err(LlmError::Network(_))), "{}", case.name);
+    }
+}